@@ -0,0 +1,17 @@
+fn main() {
+    // Only record the git commit when the `git-version` feature has been enabled.
+    //
+    // The commit is taken from the `GIT_COMMIT` environment variable provided when the
+    // application is built. We deliberately don't run `git` here: build scripts run in
+    // this crate's own directory, so doing so would record the commit of rollbar-rs
+    // rather than that of the application which depends on it.
+    if std::env::var_os("CARGO_FEATURE_GIT_VERSION").is_none() {
+        return;
+    }
+
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+
+    if let Some(commit) = std::env::var("GIT_COMMIT").ok().map(|c| c.trim().to_string()).filter(|c| !c.is_empty()) {
+        println!("cargo:rustc-env=ROLLBAR_GIT_COMMIT={}", commit);
+    }
+}
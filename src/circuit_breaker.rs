@@ -0,0 +1,188 @@
+use std::{sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use crate::clock::Clock;
+
+/// A circuit breaker which stops a transport from sending requests to Rollbar
+/// after a number of consecutive failures.
+/// 
+/// Once opened, the breaker rejects all requests until the cooldown period has
+/// elapsed, after which it allows a single probe request through (half-open).
+/// If the probe succeeds the breaker closes again, otherwise it re-opens for
+/// another cooldown period.
+#[derive(Debug)]
+pub (in crate) struct CircuitBreaker {
+    clock: Arc<dyn Clock>,
+    threshold: Option<u32>,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    opened_at: Option<Instant>,
+    probing: bool,
+}
+
+impl CircuitBreaker {
+    /// Creates a new circuit breaker which opens after `threshold` consecutive
+    /// failures. If no threshold is provided, the breaker never opens.
+    pub fn new(threshold: Option<u32>, cooldown: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            threshold,
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Determines whether a request should be allowed through the breaker, returning
+    /// a [`Permit`] which records the outcome of the request if it is.
+    pub fn allow(self: &Arc<Self>) -> Option<Permit> {
+        if self.threshold.is_none() {
+            return Some(Permit::new(self.clone(), false));
+        }
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.opened_at {
+            None => Some(Permit::new(self.clone(), false)),
+            Some(opened_at) if !state.probing && self.clock.now().duration_since(opened_at) >= self.cooldown => {
+                debug!("CircuitBreaker: Cooldown elapsed, allowing a probe request through");
+                state.probing = true;
+                Some(Permit::new(self.clone(), true))
+            },
+            _ => None,
+        }
+    }
+
+    /// Records that a request completed successfully, closing the breaker.
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.opened_at.is_some() {
+            info!("CircuitBreaker: Rollbar is reachable again, resuming sending events");
+        }
+
+        *state = BreakerState::default();
+    }
+
+    /// Records that a request failed, opening the breaker if the threshold of
+    /// consecutive failures has been reached.
+    fn record_failure(&self) {
+        let threshold = match self.threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.failures = state.failures.saturating_add(1);
+
+        if state.probing || (state.opened_at.is_none() && state.failures >= threshold) {
+            warn!("CircuitBreaker: Rollbar appears to be unavailable, events will be dropped for the next {:?}", self.cooldown);
+            state.opened_at = Some(self.clock.now());
+            state.probing = false;
+        }
+    }
+
+    /// Records that a probe request was abandoned before it reached Rollbar, allowing
+    /// another probe to be sent in its place.
+    fn abandon_probe(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.probing = false;
+    }
+}
+
+/// Permission to send a single request through a [`CircuitBreaker`].
+///
+/// The outcome of the request should be recorded using [`Permit::record_success`] or
+/// [`Permit::record_failure`]. If a probe's permit is dropped without its outcome
+/// being recorded (for example, because the event could not be serialized or the
+/// transport panicked), the breaker allows another probe through rather than
+/// remaining open indefinitely.
+#[derive(Debug)]
+#[must_use]
+pub (in crate) struct Permit {
+    breaker: Arc<CircuitBreaker>,
+    probe: bool,
+}
+
+impl Permit {
+    fn new(breaker: Arc<CircuitBreaker>, probe: bool) -> Self {
+        Self { breaker, probe }
+    }
+
+    /// Records that the request reached Rollbar, closing the breaker.
+    pub fn record_success(mut self) {
+        self.probe = false;
+        self.breaker.record_success();
+    }
+
+    /// Records that the request could not reach Rollbar, opening the breaker if the
+    /// threshold of consecutive failures has been reached.
+    pub fn record_failure(mut self) {
+        self.probe = false;
+        self.breaker.record_failure();
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.probe {
+            debug!("CircuitBreaker: The probe request was abandoned, allowing another probe through");
+            self.breaker.abandon_probe();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = Arc::new(CircuitBreaker::new(Some(2), Duration::from_millis(50), Arc::new(crate::clock::SystemClock)));
+
+        breaker.allow().expect("the breaker should start closed").record_failure();
+        let permit = breaker.allow().expect("the breaker should remain closed below the threshold");
+        permit.record_failure();
+        assert!(breaker.allow().is_none(), "the breaker should open once the threshold is reached");
+
+        std::thread::sleep(Duration::from_millis(60));
+        let probe = breaker.allow().expect("a probe should be allowed after the cooldown");
+        assert!(breaker.allow().is_none(), "only a single probe should be allowed");
+        probe.record_failure();
+        assert!(breaker.allow().is_none(), "a failed probe should re-open the breaker");
+
+        std::thread::sleep(Duration::from_millis(60));
+        breaker.allow().expect("a probe should be allowed after the cooldown").record_success();
+        assert!(breaker.allow().is_some(), "a successful probe should close the breaker");
+        assert!(breaker.allow().is_some());
+    }
+
+    #[test]
+    fn test_abandoned_probe() {
+        let clock = crate::clock::MockClock::new();
+        let breaker = Arc::new(CircuitBreaker::new(Some(1), Duration::from_secs(30), Arc::new(clock.clone())));
+
+        breaker.allow().unwrap().record_failure();
+        assert!(breaker.allow().is_none());
+
+        clock.advance(Duration::from_secs(30));
+        let probe = breaker.allow().expect("a probe should be allowed after the cooldown");
+        assert!(breaker.allow().is_none(), "only a single probe should be allowed");
+        drop(probe);
+
+        breaker.allow().expect("another probe should be allowed once the first is abandoned").record_success();
+        assert!(breaker.allow().is_some(), "a successful probe should close the breaker");
+    }
+
+    #[test]
+    fn test_disabled_circuit_breaker() {
+        let breaker = Arc::new(CircuitBreaker::new(None, Duration::from_millis(50), Arc::new(crate::clock::SystemClock)));
+
+        for _ in 0..10 {
+            breaker.allow().unwrap().record_failure();
+        }
+
+        assert!(breaker.allow().is_some(), "a breaker without a threshold should never open");
+    }
+}
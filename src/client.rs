@@ -1,11 +1,90 @@
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}, time::Duration};
 
 use crate::*;
+use crate::clock::{Clock, SystemClock};
+use crate::rate_limiter::{RateLimit, RateLimiter};
+#[cfg(any(feature = "threaded", feature = "async"))]
+use crate::transport::DeployRequest;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Client<T: Transport> {
-    transport: T,
+    transport: Arc<T>,
     config: Arc<Configuration>,
+    suppressed: Arc<AtomicU64>,
+    rate_limiter: Arc<RateLimiter>,
+    clock: Arc<dyn Clock>,
+    recent: Option<Arc<RecentReports>>,
+}
+
+/// A ring buffer which retains the most recent events reported by a client.
+#[derive(Debug)]
+struct RecentReports {
+    capacity: usize,
+    items: Mutex<VecDeque<crate::types::Data>>,
+}
+
+impl RecentReports {
+    fn push(&self, data: crate::types::Data) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut items = self.items.lock().unwrap_or_else(|e| e.into_inner());
+        if items.len() >= self.capacity {
+            items.pop_front();
+        }
+
+        items.push_back(data);
+    }
+}
+
+impl<T: Transport> Clone for Client<T> {
+    fn clone(&self) -> Self {
+        Client {
+            transport: self.transport.clone(),
+            config: self.config.clone(),
+            suppressed: self.suppressed.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            clock: self.clock.clone(),
+            recent: self.recent.clone(),
+        }
+    }
+}
+
+/// An object-safe interface for reporting events, which is implemented by every
+/// [`Client`] regardless of its transport.
+/// 
+/// This allows a client to be registered as the global default using
+/// [`crate::set_default_client`].
+pub trait Reporter: Send + Sync {
+    /// Reports a new event to Rollbar, returning whether it was queued for sending
+    /// or the reason it was dropped.
+    fn try_report(&self, data: crate::types::Data) -> ReportOutcome;
+}
+
+impl<T: Transport> Reporter for Client<T> {
+    fn try_report(&self, data: crate::types::Data) -> ReportOutcome {
+        Client::try_report(self, data)
+    }
+}
+
+/// A guard which keeps a heartbeat started by [`Client::spawn_heartbeat`] running.
+/// 
+/// The heartbeat is stopped when this guard is dropped.
+#[derive(Debug)]
+pub struct HeartbeatGuard {
+    #[cfg(not(feature = "async"))]
+    _stop: std::sync::mpsc::Sender<()>,
+
+    #[cfg(feature = "async")]
+    handle: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "async")]
+impl Drop for HeartbeatGuard {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
 }
 
 impl<T: Transport> Client<T> {
@@ -15,7 +94,113 @@ impl<T: Transport> Client<T> {
     /// chosen transport and a specific configuration. It may then be
     /// used to send errors to Rollbar instead of the default client.
     pub fn new(transport: T, config: Configuration) -> Self {
-        Client { transport, config: Arc::new(config) }
+        Client {
+            transport: Arc::new(transport),
+            config: Arc::new(config),
+            suppressed: Arc::new(AtomicU64::new(0)),
+            rate_limiter: Arc::new(RateLimiter::default()),
+            clock: Arc::new(SystemClock),
+            recent: None,
+        }
+    }
+
+    /// Replaces the clock used by this client's rate limiter, allowing its windows
+    /// to be tested deterministically using a [`crate::MockClock`] (available with
+    /// the `test-util` feature). The transport's clock is configured separately,
+    /// using [`TransportConfig::clock`].
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.rate_limiter = Arc::new(RateLimiter::new(clock.clone()));
+        self.clock = clock;
+        self
+    }
+
+    /// Retains the last `capacity` events reported by this client (and any clients
+    /// derived from it) in memory, regardless of its transport, so that they may be
+    /// retrieved using [`Client::recent_reports`]. This is useful for showing recent
+    /// errors in an in-app diagnostics panel.
+    pub fn with_recent_reports(mut self, capacity: usize) -> Self {
+        self.recent = Some(Arc::new(RecentReports {
+            capacity,
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+        }));
+        self
+    }
+
+    /// Gets the most recent events reported by this client, oldest first, when it
+    /// has been configured to retain them using [`Client::with_recent_reports`]. Only
+    /// events which were queued for sending are retained.
+    /// 
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    /// 
+    /// let client = Client::with_default_transport(Configuration::default()).unwrap()
+    ///     .with_recent_reports(10);
+    /// 
+    /// for data in client.recent_reports() {
+    ///     println!("{:?}: {:?}", data.level, data.message_text());
+    /// }
+    /// ```
+    pub fn recent_reports(&self) -> Vec<crate::types::Data> {
+        match &self.recent {
+            Some(recent) => recent.items.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect(),
+            None => vec![],
+        }
+    }
+
+    /// Gets the configuration used by this client to report events.
+    pub fn config(&self) -> &Configuration {
+        &self.config
+    }
+
+    /// Creates a new `Client` which shares this client's transport, but reports
+    /// events to the Rollbar project identified by the provided access token.
+    /// 
+    /// This allows a single process to report to several Rollbar projects (for
+    /// example, when different modules of a monorepo are tracked separately)
+    /// without needing to construct a transport for each of them.
+    /// 
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    /// 
+    /// let client = Client::with_default_transport(Configuration::default()).unwrap();
+    /// let billing = client.with_access_token("billing_project_token");
+    /// billing.report(rollbar_format!(message = "Payment declined"));
+    /// ```
+    pub fn with_access_token<S: Into<String>>(&self, access_token: S) -> Self {
+        Client {
+            transport: self.transport.clone(),
+            config: Arc::new(Configuration {
+                access_token: Some(access_token.into()),
+                ..self.config.as_ref().clone()
+            }),
+            suppressed: self.suppressed.clone(),
+            // Each project has its own quota, so it is rate limited independently.
+            rate_limiter: Arc::new(RateLimiter::new(self.clock.clone())),
+            clock: self.clock.clone(),
+            recent: self.recent.clone(),
+        }
+    }
+
+    /// Gets a snapshot of the metrics gathered by this client's transport, including
+    /// the number of events this client suppressed because they were below the
+    /// configured log level or exceeded its rate limit.
+    /// 
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    /// 
+    /// let client = Client::with_default_transport(Configuration::default()).unwrap();
+    /// client.report(rollbar_format!(Debug message = "This is below the default log level"));
+    /// assert_eq!(client.metrics().suppressed, 1);
+    /// ```
+    pub fn metrics(&self) -> TransportMetrics {
+        TransportMetrics {
+            suppressed: self.suppressed.load(Ordering::Relaxed),
+            rate_limited: self.rate_limiter.dropped(),
+            ..self.transport.metrics()
+        }
     }
 
     /// Reports a new event to Rollbar using this client.
@@ -32,20 +217,260 @@ impl<T: Transport> Client<T> {
     /// let client = Client::with_default_transport(Configuration::default()).unwrap();
     /// client.report(rollbar_format!(message = "This is a test"));
     /// ```
-    pub fn report(&self, data: crate::types::Data) {
-        let payload: models::Item = (data, self.config.as_ref()).into();
+    #[track_caller]
+    pub fn report<D: IntoReportData>(&self, data: D) {
+        self.try_report(data);
+    }
+
+    /// Reports a new event to Rollbar using this client, returning whether it
+    /// was queued for sending or the reason it was dropped.
+    /// 
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    /// 
+    /// let client = Client::with_default_transport(Configuration::default()).unwrap();
+    /// let outcome = client.try_report(rollbar_format!(message = "This is a test"));
+    /// assert_eq!(outcome, ReportOutcome::Dropped(DropReason::NoToken));
+    /// ```
+    #[track_caller]
+    pub fn try_report<D: IntoReportData>(&self, data: D) -> ReportOutcome {
+        let mut data = data.into_report_data();
+        crate::helpers::map_level(&mut data, &self.config);
+
+        // Events are filtered before they are prepared, so that the (expensive) work of
+        // symbolicating their backtraces is only done for events which will be sent.
+        let level = data.level.clone().unwrap_or(crate::Level::Info);
+        let environment = data.environment.as_deref().or(self.config.environment.as_deref());
+        if level < self.config.log_level_for(environment) {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return self.config.dropped(&data, DropReason::BelowLogLevel);
+        }
 
-        if let Some(level) = payload.data.level.clone() {
-            if level < self.config.log_level {
-                return;
+        if self.config.access_token.is_none() {
+            crate::transport::warn_no_access_token();
+            return self.config.dropped(&data, DropReason::NoToken);
+        }
+
+        if let Some((max_events, window)) = self.config.max_events_per_window {
+            match self.rate_limiter.admit(max_events, window) {
+                RateLimit::Exceeded => return self.config.dropped(&data, DropReason::RateLimited),
+                RateLimit::Allowed { suppressed: Some(suppressed) } => self.transport.send(TransportEvent {
+                    config: &self.config,
+                    payload: (crate::rate_limiter::summary(suppressed, max_events, window), self.config.as_ref()).into(),
+                }),
+                RateLimit::Allowed { suppressed: None } => {},
             }
         }
+
+        let payload: models::Item = (data, self.config.as_ref()).into();
+        let uuid = payload.data.uuid.clone().unwrap_or_default();
+
+        if let Some(recent) = &self.recent {
+            recent.push(payload.data.clone());
+        }
         
         self.transport.send(TransportEvent {
             config: &self.config,
             payload,
         });
+
+        ReportOutcome::Queued { uuid }
+    }
+
+    /// Reports a new event to Rollbar using this client, allowing it to be built
+    /// programmatically by a closure.
+    /// 
+    /// The closure is provided with an empty event (with only the `notifier` set)
+    /// which it may modify as needed, after which it is merged with this client's
+    /// configuration and sent in the same way as [`Client::try_report`].
+    /// 
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    /// 
+    /// let client = Client::with_default_transport(Configuration::default()).unwrap();
+    /// client.report_with(|data| {
+    ///     data.level = Some(Level::Warning);
+    ///     data.body = rollbar_format!(message = "Disk space is running low").body;
+    ///     data.fingerprint = Some("disk-space".to_string());
+    /// });
+    /// ```
+    pub fn report_with<F: FnOnce(&mut crate::types::Data)>(&self, f: F) -> ReportOutcome {
+        let mut data = crate::types::Data {
+            notifier: Some(crate::types::Notifier {
+                name: Some("SierraSoftworks/rollbar-rs".into()),
+                version: Some(crate::VERSION.into()),
+            }),
+            ..Default::default()
+        };
+
+        f(&mut data);
+        self.try_report(data)
+    }
+
+    /// Reports a collection of events to Rollbar using this client.
+    /// 
+    /// Each event is prepared and filtered in the same way as it would be by
+    /// [`Client::report`], making this a convenient entry point for bulk
+    /// ingestion (for example, when importing crash logs).
+    /// 
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    /// 
+    /// let client = Client::with_default_transport(Configuration::default()).unwrap();
+    /// client.report_many(vec![
+    ///     rollbar_format!(message = "First event"),
+    ///     rollbar_format!(message = "Second event"),
+    /// ]);
+    /// ```
+    pub fn report_many<I: IntoIterator>(&self, items: I)
+        where I::Item: IntoReportData
+    {
+        for data in items {
+            self.report(data);
+        }
     }
+
+    /// Re-sends any events which were spooled by this client's transport because
+    /// they could not be delivered to Rollbar, returning the number delivered.
+    /// 
+    /// This waits for the transport to finish sending each of the spooled events, and
+    /// only removes them from the spool once their delivery has been confirmed; events
+    /// which could not be delivered remain in the spool to be replayed later. Events
+    /// older than the spool's maximum age are discarded without being sent, and files
+    /// which cannot be read are left in place. This may be used to trigger recovery
+    /// manually, for example in response to an administrative command.
+    /// 
+    /// As this blocks until the events have been sent, it should be called from a
+    /// blocking context (like `tokio::task::spawn_blocking`) when using the async
+    /// transport.
+    /// 
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    /// 
+    /// let client = Client::new(ThreadedTransport::new(&TransportConfig {
+    ///     spool_dir: Some(std::env::temp_dir().join("rollbar-spool")),
+    ///     ..Default::default()
+    /// }).unwrap(), Configuration::default());
+    /// 
+    /// let replayed = client.replay_spool().unwrap();
+    /// ```
+    pub fn replay_spool(&self) -> Result<usize, Error> {
+        let spool = match self.transport.spool() {
+            Some(spool) => spool,
+            None => return Ok(0),
+        };
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        for path in spool.entries()? {
+            match spool.read(&path) {
+                Ok(payload) if spool.is_expired(&path, &payload) => {
+                    debug!("Discarding stale spooled Rollbar event {}", path.display());
+                    spool.remove(&path)?;
+                },
+                Ok(payload) => {
+                    let tx = tx.clone();
+                    self.transport.send_with_callback(TransportEvent {
+                        config: &self.config,
+                        payload,
+                    }, Box::new(move |delivered| {
+                        tx.send((path, delivered)).ok();
+                    }));
+                },
+                Err(e) => warn!("Skipping spooled Rollbar event {}: {}", path.display(), e),
+            }
+        }
+
+        // Callbacks which are dropped without being invoked close the channel, so this
+        // completes once every replayed event has either been delivered or abandoned.
+        drop(tx);
+
+        let mut replayed = 0;
+        for (path, delivered) in rx {
+            if delivered {
+                spool.remove(&path)?;
+                replayed += 1;
+            } else {
+                debug!("Keeping spooled Rollbar event {} since it could not be delivered", path.display());
+            }
+        }
+
+        Ok(replayed)
+    }
+
+    /// Periodically reports a heartbeat event to Rollbar until the returned guard
+    /// is dropped.
+    /// 
+    /// Heartbeats are small message events, carrying `custom.heartbeat = true` so
+    /// that they can be filtered, which allow you to confirm that your reporting
+    /// pipeline is working end-to-end. A small amount of jitter is added to the
+    /// interval to avoid many processes reporting in lockstep.
+    /// 
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    /// 
+    /// let client = Client::with_default_transport(Configuration::default()).unwrap();
+    /// let _heartbeat = client.spawn_heartbeat(std::time::Duration::from_secs(60), Level::Info);
+    /// ```
+    #[cfg(not(feature = "async"))]
+    pub fn spawn_heartbeat(&self, interval: Duration, level: Level) -> HeartbeatGuard
+        where T: 'static
+    {
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let client = self.clone();
+
+        std::thread::spawn(move || {
+            while let Err(std::sync::mpsc::RecvTimeoutError::Timeout) = rx.recv_timeout(jittered(interval)) {
+                client.report(heartbeat(level.clone()));
+            }
+        });
+
+        HeartbeatGuard { _stop: tx }
+    }
+
+    /// Periodically reports a heartbeat event to Rollbar until the returned guard
+    /// is dropped.
+    /// 
+    /// Heartbeats are small message events, carrying `custom.heartbeat = true` so
+    /// that they can be filtered, which allow you to confirm that your reporting
+    /// pipeline is working end-to-end. A small amount of jitter is added to the
+    /// interval to avoid many processes reporting in lockstep.
+    #[cfg(feature = "async")]
+    pub fn spawn_heartbeat(&self, interval: Duration, level: Level) -> HeartbeatGuard
+        where T: 'static
+    {
+        let client = self.clone();
+
+        let handle = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(jittered(interval)).await;
+                client.report(heartbeat(level.clone()));
+            }
+        });
+
+        HeartbeatGuard { handle }
+    }
+}
+
+/// Builds the event which is reported for each heartbeat.
+fn heartbeat(level: Level) -> crate::types::Data {
+    let mut data = crate::rollbar_format!(message = "Heartbeat", custom = crate::map!{ heartbeat: true });
+    data.level = Some(level);
+    data
+}
+
+/// Adds up to 10% of jitter to the provided interval.
+fn jittered(interval: Duration) -> Duration {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+
+    interval + interval.mul_f64((nanos % 1000) as f64 / 10000.0)
 }
 
 
@@ -54,6 +479,31 @@ impl Client<TokioTransport> {
     pub fn with_default_transport(config: Configuration) -> Result<Self, Error> {
         Ok(Client::new(TokioTransport::new(&TransportConfig::default())?, config))
     }
+
+    /// Records a deploy of the provided revision to an environment using Rollbar's
+    /// deploy API, returning the ID of the deploy.
+    /// 
+    /// # Example
+    /// ```rust,no_run
+    /// use rollbar_rs::*;
+    /// 
+    /// # async fn deploy() {
+    /// let client = Client::with_default_transport(Configuration {
+    ///     access_token: Some("...".to_string()),
+    ///     ..Default::default()
+    /// }).unwrap();
+    /// 
+    /// let deploy_id = client.report_deploy("production", env!("CARGO_PKG_VERSION"), Some("Released by CI".to_string())).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn report_deploy(&self, environment: &str, revision: &str, comment: Option<String>) -> Result<u64, Error> {
+        let access_token = deploy_access_token(&self.config)?;
+        self.transport.send_deploy(access_token, &DeployRequest {
+            environment,
+            revision,
+            comment: comment.as_deref(),
+        }).await
+    }
 }
 
 #[cfg(feature = "threaded")]
@@ -61,4 +511,582 @@ impl Client<ThreadedTransport> {
     pub fn with_default_transport(config: Configuration) -> Result<Self, Error> {
         Ok(Client::new(ThreadedTransport::new(&TransportConfig::default())?, config))
     }
-}
\ No newline at end of file
+
+    /// Records a deploy of the provided revision to an environment using Rollbar's
+    /// deploy API, returning the ID of the deploy.
+    /// 
+    /// This blocks until Rollbar has responded, making it suitable for use from
+    /// deploy scripts and release tooling.
+    /// 
+    /// # Example
+    /// ```rust,no_run
+    /// use rollbar_rs::*;
+    /// 
+    /// let client = Client::with_default_transport(Configuration {
+    ///     access_token: Some("...".to_string()),
+    ///     ..Default::default()
+    /// }).unwrap();
+    /// 
+    /// let deploy_id = client.report_deploy("production", env!("CARGO_PKG_VERSION"), Some("Released by CI".to_string())).unwrap();
+    /// ```
+    pub fn report_deploy(&self, environment: &str, revision: &str, comment: Option<String>) -> Result<u64, Error> {
+        let access_token = deploy_access_token(&self.config)?;
+        self.transport.send_deploy(access_token, &DeployRequest {
+            environment,
+            revision,
+            comment: comment.as_deref(),
+        })
+    }
+}
+
+/// Gets the access token which should be used to record a deploy.
+#[cfg(any(feature = "threaded", feature = "async"))]
+fn deploy_access_token(config: &Configuration) -> Result<&str, Error> {
+    config.access_token.as_deref().ok_or_else(|| crate::errors::user(
+        "We could not record the deploy because no Rollbar access token has been configured.",
+        "Make sure that you have provided an access token with the post_server_item scope and try again.",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::*;
+    use crate::transport::MockTransport;
+
+    fn test_config() -> Configuration {
+        Configuration {
+            access_token: Some("test_token".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_try_report() {
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), test_config());
+
+        assert_eq!(client.try_report(rollbar_format!(Debug message = "Below the log level")), ReportOutcome::Dropped(DropReason::BelowLogLevel));
+
+        match client.try_report(rollbar_format!(message = "Queued", uuid = "my-uuid")) {
+            ReportOutcome::Queued { uuid } => assert_eq!(uuid, "my-uuid"),
+            other => panic!("Expected the event to be queued, got {:?}", other),
+        }
+
+        let client = Client::new(transport.clone(), Configuration::default());
+        assert_eq!(client.try_report(rollbar_format!(message = "Without a token")), ReportOutcome::Dropped(DropReason::NoToken));
+        assert!(crate::transport::MISSING_TOKEN_WARNED.load(std::sync::atomic::Ordering::Relaxed), "dropping a tokenless event should warn about the missing token");
+
+        assert_eq!(transport.items().len(), 1);
+    }
+
+    #[test]
+    fn test_filtered_events_are_not_symbolicated() {
+        let _lock = crate::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), Configuration {
+            log_level: Level::Error,
+            ..test_config()
+        });
+
+        let symbolications = || crate::helpers::SYMBOLICATIONS.with(|count| count.get());
+        let before = symbolications();
+
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+        let mut data = rollbar_format!(error = err);
+        data.level = Some(Level::Warning);
+        assert_eq!(client.try_report(data), ReportOutcome::Dropped(DropReason::BelowLogLevel));
+        assert_eq!(symbolications(), before, "a filtered event should never be symbolicated");
+
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+        let mut data = rollbar_format!(error = err);
+        data.level = Some(Level::Error);
+        assert!(matches!(client.try_report(data), ReportOutcome::Queued { .. }));
+        assert_eq!(symbolications(), before + 1, "a reported event should be symbolicated");
+
+        let items = transport.items();
+        assert_eq!(items.len(), 1);
+        match &items[0].data.body {
+            crate::types::Body::TraceBody { trace, .. } => assert!(!trace.frames.iter().any(|frame| frame.filename == crate::helpers::UNRESOLVED_FRAME), "the reported event should not include unresolved frames"),
+            _ => panic!("Expected trace body"),
+        }
+    }
+
+    #[test]
+    fn test_capture_backtrace() {
+        let _lock = crate::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let transport = MockTransport::default();
+        let without_backtrace = Client::new(transport.clone(), Configuration {
+            capture_backtrace: false,
+            ..test_config()
+        });
+        let with_backtrace = Client::new(transport.clone(), test_config());
+
+        // The global configuration should not affect clients which have their own.
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+        crate::with_config(Configuration { capture_backtrace: false, ..Default::default() }, || {
+            without_backtrace.report(rollbar_format!(error = err));
+            with_backtrace.report(rollbar_format!(error = err));
+        });
+
+        let frames: Vec<Vec<crate::types::Frame>> = transport.items().into_iter()
+            .map(|item| match item.data.body {
+                crate::types::Body::TraceBody { trace, .. } => trace.frames,
+                _ => panic!("Expected trace body"),
+            })
+            .collect();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].len(), 1, "only the call-site frame should be included when backtraces are disabled");
+        assert_eq!(frames[0][0].filename, file!().to_string());
+        assert!(frames[1].len() > 1, "the backtrace should be included when it is enabled");
+        assert!(!frames[1].iter().any(|frame| frame.filename == crate::helpers::UNRESOLVED_FRAME), "the backtrace should be symbolicated");
+    }
+
+    #[test]
+    fn test_on_drop() {
+        let dropped = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = Client::new(MockTransport::default(), Configuration {
+            on_drop: Some({
+                let dropped = dropped.clone();
+                std::sync::Arc::new(move |data: &crate::types::Data, reason| {
+                    dropped.lock().unwrap().push((data.message_text().map(|m| m.to_string()), reason));
+                })
+            }),
+            ..test_config()
+        });
+
+        client.report(rollbar_format!(message = "Reported"));
+        client.report(rollbar_format!(Debug message = "Below the log level"));
+
+        assert_eq!(*dropped.lock().unwrap(), vec![(Some("Below the log level".to_string()), DropReason::BelowLogLevel)]);
+    }
+
+    #[test]
+    fn test_on_drop_without_extra_data() {
+        let dropped = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = Client::new(MockTransport::default(), Configuration {
+            on_drop: Some({
+                let dropped = dropped.clone();
+                std::sync::Arc::new(move |data: &crate::types::Data, _reason| {
+                    dropped.lock().unwrap().push(data.custom.clone());
+                })
+            }),
+            ..test_config()
+        });
+
+        client.report(rollbar_format!(Debug message = "Below the log level", custom = map!{ owner: "Bob" }, extra_data = map!{ attributes: ["beta"] }));
+
+        let dropped = dropped.lock().unwrap();
+        assert_eq!(dropped.len(), 1);
+        let custom = dropped[0].as_ref().expect("the event's custom data should be kept");
+        assert_eq!(custom.get("owner"), Some(&serde_json::json!("Bob")));
+        assert!(!custom.contains_key(crate::models::EXTRA_DATA_KEY), "extra data should not be exposed through the event's custom data");
+    }
+
+    #[test]
+    fn test_recent_reports() {
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), test_config());
+        client.report(rollbar_format!(message = "Not retained"));
+        assert!(client.recent_reports().is_empty(), "reports should only be retained when enabled");
+
+        let client = client.with_recent_reports(3);
+        for i in 0..5 {
+            client.report(rollbar_format!(message = format!("Event {}", i)));
+        }
+
+        client.report(rollbar_format!(Debug message = "Below the log level"));
+
+        let messages: Vec<String> = client.recent_reports().iter()
+            .filter_map(|data| data.message_text().map(|m| m.to_string()))
+            .collect();
+        assert_eq!(messages, vec!["Event 2", "Event 3", "Event 4"]);
+    }
+
+    #[test]
+    fn test_level_mapper() {
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), Configuration {
+            level_mapper: Some(std::sync::Arc::new(|exception: &crate::types::Exception| {
+                if exception.class == "std::io::error::Error" {
+                    Some(Level::Warning)
+                } else {
+                    None
+                }
+            })),
+            ..test_config()
+        });
+
+        let err = std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+        client.report(rollbar_format!(Error error = err));
+
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+        client.report(rollbar_format!(Error error = err));
+
+        let items = transport.items();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].data.level, Some(Level::Warning), "the mapped exception class should be reported as a warning");
+        assert_eq!(items[1].data.level, Some(Level::Error), "other exceptions should keep their level");
+    }
+
+    #[test]
+    fn test_with_access_token() {
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), test_config());
+        let billing = client.with_access_token("billing_token");
+
+        assert_eq!(client.config().access_token, Some("test_token".to_string()));
+        assert_eq!(billing.config().access_token, Some("billing_token".to_string()));
+
+        billing.report(rollbar_format!(message = "Payment declined"));
+        assert_eq!(transport.items().len(), 1, "the transport should be shared between clients");
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_with_access_token_header() {
+        use httptest::{Server, Expectation, matchers::*, responders::*};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/api/1/item/"),
+                request::headers(contains(("x-rollbar-access-token", "billing_token"))),
+            ])
+            .times(1)
+            .respond_with(status_code(200))
+        );
+
+        let transport = ThreadedTransport::new(&TransportConfig {
+            endpoint: server.url("/api/1/item/").to_string(),
+            ..Default::default()
+        }).unwrap();
+
+        let client = Client::new(transport, test_config());
+        client.with_access_token("billing_token").report(rollbar_format!(message = "Payment declined"));
+    }
+
+    #[test]
+    fn test_suppressed_metric() {
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), Configuration {
+            log_level: Level::Warning,
+            ..test_config()
+        });
+
+        client.report(rollbar_format!(Debug message = "Below the log level"));
+        client.report(rollbar_format!(Info message = "Below the log level"));
+        assert_eq!(client.metrics().suppressed, 2);
+        assert!(transport.items().is_empty(), "suppressed events should not reach the transport");
+
+        client.report(rollbar_format!(Error message = "Above the log level"));
+        assert_eq!(client.metrics().suppressed, 2);
+        assert_eq!(transport.items().len(), 1);
+    }
+
+    #[test]
+    fn test_report_into_data() {
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), test_config());
+
+        client.report("Something happened");
+        client.report(String::from("Something else happened"));
+
+        let items = transport.items();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].data.message_text(), Some("Something happened"));
+        assert_eq!(items[1].data.message_text(), Some("Something else happened"));
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_report_deploy() {
+        use httptest::{Server, Expectation, matchers::*, responders::*};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/api/1/deploy/"),
+                request::headers(contains(("x-rollbar-access-token", "test_token"))),
+                request::body(json_decoded(eq(serde_json::json!({
+                    "environment": "production",
+                    "revision": "abc123",
+                    "comment": "Released by CI",
+                })))),
+            ])
+            .respond_with(json_encoded(serde_json::json!({ "err": 0, "data": { "deploy_id": 42 } })))
+        );
+
+        let transport = ThreadedTransport::new(&TransportConfig {
+            endpoint: server.url("/api/1/item/").to_string(),
+            ..Default::default()
+        }).unwrap();
+
+        let client = Client::new(transport, test_config());
+        assert_eq!(client.report_deploy("production", "abc123", Some("Released by CI".to_string())).unwrap(), 42);
+
+        let client = Client { config: std::sync::Arc::new(Configuration::default()), ..client };
+        assert!(client.report_deploy("production", "abc123", None).is_err(), "a deploy cannot be recorded without an access token");
+    }
+
+    #[test]
+    fn test_max_events_per_window() {
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), Configuration {
+            max_events_per_window: Some((3, Duration::from_secs(60))),
+            ..test_config()
+        });
+
+        for _ in 0..3 {
+            assert!(client.try_report(rollbar_format!(message = "Within the limit")).is_queued());
+        }
+
+        assert_eq!(client.try_report(rollbar_format!(message = "Over the limit")), ReportOutcome::Dropped(DropReason::RateLimited));
+        assert_eq!(client.try_report(rollbar_format!(message = "Over the limit")), ReportOutcome::Dropped(DropReason::RateLimited));
+
+        assert_eq!(transport.items().len(), 3, "events over the limit should not reach the transport");
+        assert_eq!(client.metrics().rate_limited, 2);
+    }
+
+    #[test]
+    fn test_max_events_per_window_summary() {
+        let clock = crate::MockClock::new();
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), Configuration {
+            max_events_per_window: Some((1, Duration::from_secs(60))),
+            ..test_config()
+        }).with_clock(std::sync::Arc::new(clock.clone()));
+
+        assert!(client.try_report(rollbar_format!(message = "Within the limit")).is_queued());
+        assert_eq!(client.try_report(rollbar_format!(message = "Over the limit")), ReportOutcome::Dropped(DropReason::RateLimited));
+        assert_eq!(client.try_report(rollbar_format!(message = "Over the limit")), ReportOutcome::Dropped(DropReason::RateLimited));
+
+        clock.advance(Duration::from_secs(60));
+        assert!(client.try_report(rollbar_format!(message = "After the window")).is_queued());
+
+        let items = transport.items();
+        assert_eq!(items.len(), 3, "the summary should be sent along with the events within the limit");
+
+        let summary = &items[1].data;
+        assert_eq!(summary.level, Some(Level::Warning));
+        assert!(summary.message_text().unwrap_or_default().starts_with("2 events were dropped"), "the summary should describe the dropped events");
+        assert_eq!(summary.custom.as_ref().and_then(|custom| custom.get("rate_limited")), Some(&serde_json::json!(2)));
+        assert_eq!(items[2].data.message_text(), Some("After the window"));
+    }
+
+    #[test]
+    fn test_report_many() {
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), test_config());
+
+        client.report_many(vec![
+            rollbar_format!(message = "First event"),
+            rollbar_format!(Debug message = "Filtered event"),
+            rollbar_format!(Warning message = "Second event"),
+            rollbar_format!(Error message = "Third event"),
+        ]);
+
+        let items = transport.items();
+        assert_eq!(items.len(), 3, "events below the log level should be filtered");
+        assert_eq!(items[0].data.level, Some(Level::Info));
+        assert_eq!(items[1].data.level, Some(Level::Warning));
+        assert_eq!(items[2].data.level, Some(Level::Error));
+    }
+
+    #[test]
+    #[cfg(not(feature = "async"))]
+    fn test_spawn_heartbeat() {
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), test_config());
+
+        let guard = client.spawn_heartbeat(std::time::Duration::from_millis(10), Level::Info);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        drop(guard);
+
+        let items = transport.items();
+        assert!(items.len() >= 2, "at least two heartbeats should have been reported");
+        for item in items {
+            assert_eq!(item.data.custom.unwrap()["heartbeat"], true);
+        }
+    }
+
+    #[test]
+    fn test_replay_compressed_spool() {
+        let dir = std::env::temp_dir().join(format!("rollbar-rs-replay-{}", helpers::new_uuid()));
+        let spool = Spool::new(&dir).with_compression(true);
+
+        let item: models::Item = (rollbar_format!(message = "Compressed event"), &test_config()).into();
+        spool.write(&item).unwrap();
+
+        let transport = MockTransport::with_spool(spool.clone());
+        let client = Client::new(transport.clone(), test_config());
+
+        assert_eq!(client.replay_spool().unwrap(), 1);
+
+        let items = transport.items();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].data.uuid, item.data.uuid);
+        assert_eq!(items[0].data.message_text(), Some("Compressed event"));
+        assert!(spool.entries().unwrap().is_empty(), "the replayed event should be removed from the spool");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_replay_spool() {
+        let dir = std::env::temp_dir().join(format!("rollbar-rs-replay-{}", helpers::new_uuid()));
+        let spool = Spool::new(&dir);
+
+        let first: models::Item = (rollbar_format!(message = "First event"), &test_config()).into();
+        let second: models::Item = (rollbar_format!(message = "Second event"), &test_config()).into();
+        spool.write(&first).unwrap();
+        spool.write(&second).unwrap();
+        std::fs::write(dir.join("corrupt.json"), "not json").unwrap();
+
+        let transport = MockTransport::with_spool(spool);
+        let client = Client::new(transport.clone(), test_config());
+
+        assert_eq!(client.replay_spool().unwrap(), 2);
+
+        let mut uuids: Vec<Option<String>> = transport.items().into_iter().map(|i| i.data.uuid).collect();
+        uuids.sort();
+        let mut expected = vec![first.data.uuid, second.data.uuid];
+        expected.sort();
+        assert_eq!(uuids, expected);
+
+        let remaining: Vec<_> = std::fs::read_dir(&dir).unwrap().filter_map(|e| e.ok()).map(|e| e.file_name()).collect();
+        assert_eq!(remaining, vec![std::ffi::OsString::from("corrupt.json")], "only the unreadable file should remain");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_replay_spool_undelivered() {
+        let dir = std::env::temp_dir().join(format!("rollbar-rs-replay-{}", helpers::new_uuid()));
+        let spool = Spool::new(&dir);
+
+        let item: models::Item = (rollbar_format!(message = "Undeliverable event"), &test_config()).into();
+        spool.write(&item).unwrap();
+
+        let transport = MockTransport::with_spool(spool.clone()).undeliverable();
+        let client = Client::new(transport.clone(), test_config());
+
+        assert_eq!(client.replay_spool().unwrap(), 0, "undelivered events should not be counted as replayed");
+        assert_eq!(transport.items().len(), 1, "the event should have been sent to the transport");
+        assert_eq!(spool.entries().unwrap().len(), 1, "undelivered events should remain in the spool");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_log_levels_by_environment() {
+        let mut config = test_config();
+        config.log_levels_by_environment.insert("production".to_string(), Level::Warning);
+
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), config);
+
+        assert_eq!(client.try_report(rollbar_format!(Info message = "Production event", environment = "production")), ReportOutcome::Dropped(DropReason::BelowLogLevel));
+        assert!(client.try_report(rollbar_format!(Info message = "Development event", environment = "development")).is_queued());
+        assert!(client.try_report(rollbar_format!(Warning message = "Production warning", environment = "production")).is_queued());
+
+        assert_eq!(transport.items().len(), 2);
+    }
+
+    #[test]
+    fn test_replay_spool_max_age() {
+        let dir = std::env::temp_dir().join(format!("rollbar-rs-replay-{}", helpers::new_uuid()));
+        let spool = Spool::new(&dir).with_max_age(Duration::from_secs(3600));
+
+        let fresh: models::Item = (rollbar_format!(message = "Fresh event"), &test_config()).into();
+        let stale: models::Item = (rollbar_format!(message = "Stale event", timestamp = helpers::now_timestamp() - 2 * 24 * 3600), &test_config()).into();
+        spool.write(&fresh).unwrap();
+        spool.write(&stale).unwrap();
+
+        let transport = MockTransport::with_spool(spool.clone());
+        let client = Client::new(transport.clone(), test_config());
+
+        assert_eq!(client.replay_spool().unwrap(), 1);
+
+        let items = transport.items();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].data.uuid, fresh.data.uuid);
+        assert!(spool.entries().unwrap().is_empty(), "stale events should be removed from the spool");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    #[cfg(feature = "threaded")]
+    fn test_replay_preserves_uuid() {
+        use httptest::{Server, Expectation, matchers::*, responders::*};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .respond_with(status_code(503))
+        );
+
+        let dir = std::env::temp_dir().join(format!("rollbar-rs-replay-{}", helpers::new_uuid()));
+
+        let uuid = {
+            let client = Client::new(ThreadedTransport::new(&TransportConfig {
+                endpoint: server.url("/api/1/item/").to_string(),
+                spool_dir: Some(dir.clone()),
+                ..Default::default()
+            }).unwrap(), test_config());
+
+            match client.try_report(rollbar_format!(message = "Undeliverable event")) {
+                ReportOutcome::Queued { uuid } => uuid,
+                outcome => panic!("Expected the event to be queued, got {:?}", outcome),
+            }
+        };
+
+        let spool = Spool::new(&dir);
+        let spooled = spool.entries().unwrap();
+        assert_eq!(spooled.len(), 1, "the undeliverable event should have been spooled");
+        assert_eq!(spool.read(&spooled[0]).unwrap().data.uuid.as_deref(), Some(uuid.as_str()));
+
+        let transport = MockTransport::with_spool(spool);
+        let client = Client::new(transport.clone(), test_config());
+        assert_eq!(client.replay_spool().unwrap(), 1);
+        assert_eq!(transport.items()[0].data.uuid.as_deref(), Some(uuid.as_str()), "replayed events should keep their original uuid");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_client_config() {
+        let client = Client::new(MockTransport::default(), test_config());
+        assert_eq!(client.config().access_token, Some("test_token".to_string()));
+    }
+
+    #[test]
+    fn test_report_with() {
+        let transport = MockTransport::default();
+        let client = Client::new(transport.clone(), test_config());
+
+        let outcome = client.report_with(|data| {
+            data.level = Some(Level::Warning);
+            data.body = crate::types::Body::MessageBody {
+                telemetry: None,
+                message: crate::types::Message {
+                    body: "Built with a closure".to_string(),
+                    extra: Default::default(),
+                },
+            };
+        });
+
+        assert!(outcome.is_queued());
+
+        let items = transport.items();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].data.level, Some(Level::Warning));
+        assert_eq!(items[0].data.message_text(), Some("Built with a closure"));
+        assert!(items[0].data.notifier.is_some());
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::{Instant, SystemTime};
+
+#[cfg(any(test, feature = "test-util"))]
+use std::{sync::{Arc, Mutex}, time::Duration};
+
+/// A source of the current time, used by the time-based features of this crate
+/// (like rate limiting, the circuit breaker and the expiry of spooled events).
+///
+/// The [`SystemClock`] is used by default, however a [`MockClock`] (available with
+/// the `test-util` feature) may be provided in its place to test these features
+/// deterministically.
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    /// Gets the current (monotonic) instant, used to measure elapsed time.
+    fn now(&self) -> Instant;
+
+    /// Gets the current wall-clock time, used to determine the age of events.
+    fn system_time(&self) -> SystemTime;
+}
+
+/// A clock which reports the system's current time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock which only moves forward when it is advanced, allowing time-based
+/// features to be tested without waiting for time to pass.
+///
+/// Clones of a `MockClock` share the same time, so a test may keep a clone of the
+/// clock it provides to a client or transport and advance it as needed.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    started: Instant,
+    started_system: SystemTime,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockClock {
+    /// Creates a new mock clock, starting at the current time.
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            started_system: SystemTime::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Moves the clock (and all of its clones) forward by the provided duration.
+    pub fn advance(&self, by: Duration) {
+        let mut elapsed = self.elapsed.lock().unwrap_or_else(|e| e.into_inner());
+        *elapsed += by;
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.started + self.elapsed()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.started_system + self.elapsed()
+    }
+}
@@ -1,8 +1,27 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
 
 use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A callback which is used to determine the `context` for an event at the
+/// time it is reported.
+pub type ContextFn = Arc<dyn Fn() -> Option<String> + Send + Sync>;
+
+/// A callback which is used to compute the `fingerprint` for an event which does
+/// not have one set explicitly, returning `None` to leave it unset.
+pub type FingerprintFn = Arc<dyn Fn(&crate::types::Data) -> Option<String> + Send + Sync>;
+
+/// A callback which is invoked whenever an event is dropped instead of being sent
+/// to Rollbar, along with the reason it was dropped.
+pub type DropHook = Arc<dyn Fn(&crate::types::Data, crate::DropReason) + Send + Sync>;
+
+/// A callback which is used to remap the level of an error event based on its
+/// exception, returning `None` to keep the event's existing level.
+pub type LevelMapper = Arc<dyn Fn(&crate::types::Exception) -> Option<crate::types::Level> + Send + Sync>;
+
+/// The name fragments which identify sensitive fields by default.
+const DEFAULT_SCRUB_FIELDS: &[&str] = &["passwd", "password", "secret", "token", "api_key", "apikey", "access_key", "auth", "credential", "private_key"];
+
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Configuration {
     pub access_token: Option<String>,
@@ -14,6 +33,114 @@ pub struct Configuration {
     pub framework: Option<String>,
     pub context: Option<String>,
     pub custom: Option<HashMap<String, serde_json::Value>>,
+
+    /// A callback which is invoked to determine the `context` for events which
+    /// do not have one set explicitly. This allows frameworks to inject the
+    /// active route or handler name at the time an event is reported.
+    #[serde(skip)]
+    pub context_fn: Option<ContextFn>,
+
+    /// Whether a fingerprint should be computed automatically for error events
+    /// which do not have one set explicitly. The fingerprint is derived from the
+    /// exception's class and the methods of the top application frames, ensuring
+    /// that it remains stable across builds.
+    pub auto_fingerprint: bool,
+
+    /// A callback which is invoked to compute the fingerprint for events which do
+    /// not have one set explicitly, allowing you to implement your own grouping logic
+    /// (for example, grouping database errors by the shape of their SQL statement).
+    /// When it returns `None`, the `auto_fingerprint` is used instead (if enabled).
+    #[serde(skip)]
+    pub fingerprint_fn: Option<FingerprintFn>,
+
+    /// Whether the `platform` should be detected from the hosting environment
+    /// (for example, `aws-lambda` or `kubernetes`) rather than using the OS. The
+    /// detected platform takes precedence over the configured `platform`, which
+    /// is used as a fallback when no hosting environment is recognized.
+    pub detect_platform: bool,
+
+    /// Whether the `uuid` of each event should be derived from its content, rather
+    /// than generated randomly. This causes identical events reported by different
+    /// processes (for example, every replica of a service) to be deduplicated by
+    /// Rollbar, however it also means that repeated occurrences of an identical event
+    /// will be deduplicated.
+    pub deterministic_uuid: bool,
+
+    /// The maximum number of frames which will be included in a trace. Deeply
+    /// recursive errors can produce enormous backtraces, so the most recent frames
+    /// are kept and the number which were omitted is recorded in the event's
+    /// `custom.frames_truncated` field. Set to `None` to disable truncation.
+    pub max_frames: Option<usize>,
+
+    /// Whether the name and identifier of the thread which reported an event should
+    /// be included in its `custom.thread_name` and `custom.thread_id` fields, which
+    /// helps to diagnose concurrency issues in multi-threaded services.
+    pub capture_thread: bool,
+
+    /// A prefix which is added to the body of every message (but not to errors),
+    /// such as `"[billing] "`, making it easier to identify where messages came from
+    /// when scanning Rollbar. Messages which already start with the prefix are not
+    /// prefixed a second time.
+    pub message_prefix: Option<String>,
+
+    /// The minimum level at which events will be reported for specific environments,
+    /// taking precedence over the `log_level` for events in those environments. This
+    /// allows a single binary to report `Debug` events in staging, while only reporting
+    /// `Warning` events (and above) in production.
+    pub log_levels_by_environment: HashMap<String, crate::types::Level>,
+
+    /// The only `custom` keys which will be sent to Rollbar. When set, any other
+    /// keys (including those added by this crate, like `request_id`) are removed
+    /// from events before they are sent, which is stricter than scrubbing and may
+    /// be required by some compliance regimes.
+    pub allowed_custom_keys: Option<Vec<String>>,
+
+    /// The environment variables which will be captured into the `custom.env` field
+    /// of each event. No variables are captured by default to avoid leaking secrets,
+    /// and the values of any variables which match the `scrub_fields` are redacted.
+    pub capture_env: Option<Vec<String>>,
+
+    /// The (case-insensitive) name fragments which identify sensitive fields, whose
+    /// values are replaced with `"***"` when they are captured. This includes the
+    /// query parameters of an event's `request.url`.
+    pub scrub_fields: Vec<String>,
+
+    /// The maximum number of events which will be reported within a window of time,
+    /// such as `(100, Duration::from_secs(60))`, which protects your Rollbar quota
+    /// from floods of events. Events over the limit are dropped, and a summary of
+    /// the number which were dropped is reported once events are allowed again.
+    pub max_events_per_window: Option<(u32, std::time::Duration)>,
+
+    /// Whether a backtrace should be included in error events reported using this
+    /// configuration (default: `true`). Symbolicating a backtrace is expensive, so
+    /// services which report errors at a high rate may disable this, in which case
+    /// their traces only include the frame of the location at which the error was
+    /// reported.
+    pub capture_backtrace: bool,
+
+    /// A callback which may remap the level of error events based on their exception
+    /// (for example, reporting `tokio::time::error::Elapsed` at the `Warning` level),
+    /// centralizing your severity policy rather than deciding it at each call site.
+    /// The remapped level is used when filtering events by their log level, however
+    /// events reported using the global client which are below the global `log_level`
+    /// are dropped before they are remapped.
+    #[serde(skip)]
+    pub level_mapper: Option<LevelMapper>,
+
+    /// A callback which is invoked whenever an event is dropped (for example, because
+    /// it is below the log level or exceeded the rate limit), providing continuous
+    /// visibility into what is being suppressed without using `try_report` at every
+    /// call site.
+    #[serde(skip)]
+    pub on_drop: Option<DropHook>,
+
+    /// The window within which identical panics (with the same location and message)
+    /// reported by `handle_panics!` on any thread are coalesced. The first panic is
+    /// reported immediately, and the next identical panic after the window records the
+    /// number of panics which were suppressed in its `custom.occurrences` field. This
+    /// prevents a panic storm from exhausting your quota. When not set, every panic is
+    /// reported.
+    pub panic_coalesce_window: Option<std::time::Duration>,
 }
 
 impl Default for Configuration {
@@ -25,9 +152,397 @@ impl Default for Configuration {
             platform: Some(std::env::consts::OS.to_string()),
             framework: None,
             context: None,
+            context_fn: None,
+            auto_fingerprint: false,
+            fingerprint_fn: None,
+            detect_platform: false,
+            deterministic_uuid: false,
+            max_frames: Some(100),
+            capture_thread: false,
+            message_prefix: None,
+            log_levels_by_environment: HashMap::new(),
+            allowed_custom_keys: None,
+            capture_env: None,
+            scrub_fields: DEFAULT_SCRUB_FIELDS.iter().map(|f| f.to_string()).collect(),
+            max_events_per_window: None,
+            capture_backtrace: true,
+            level_mapper: None,
+            on_drop: None,
+            panic_coalesce_window: None,
             custom: None,
             code_version: None,
             log_level: crate::types::Level::Info,
         }
     }
-}
\ No newline at end of file
+}
+
+impl Debug for Configuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Configuration")
+            .field("access_token", &self.access_token)
+            .field("environment", &self.environment)
+            .field("host", &self.host)
+            .field("code_version", &self.code_version)
+            .field("log_level", &self.log_level)
+            .field("platform", &self.platform)
+            .field("framework", &self.framework)
+            .field("context", &self.context)
+            .field("custom", &self.custom)
+            .field("context_fn", &self.context_fn.as_ref().map(|_| "<fn>"))
+            .field("auto_fingerprint", &self.auto_fingerprint)
+            .field("fingerprint_fn", &self.fingerprint_fn.as_ref().map(|_| "<fn>"))
+            .field("detect_platform", &self.detect_platform)
+            .field("deterministic_uuid", &self.deterministic_uuid)
+            .field("max_frames", &self.max_frames)
+            .field("capture_thread", &self.capture_thread)
+            .field("message_prefix", &self.message_prefix)
+            .field("log_levels_by_environment", &self.log_levels_by_environment)
+            .field("allowed_custom_keys", &self.allowed_custom_keys)
+            .field("capture_env", &self.capture_env)
+            .field("scrub_fields", &self.scrub_fields)
+            .field("max_events_per_window", &self.max_events_per_window)
+            .field("capture_backtrace", &self.capture_backtrace)
+            .field("level_mapper", &self.level_mapper.as_ref().map(|_| "<fn>"))
+            .field("on_drop", &self.on_drop.as_ref().map(|_| "<fn>"))
+            .field("panic_coalesce_window", &self.panic_coalesce_window)
+            .finish()
+    }
+}
+
+impl Configuration {
+    /// Merges another configuration into this one, filling in any fields which
+    /// have not been set on this configuration.
+    ///
+    /// Values on `self` always take precedence over those on `other`, allowing you
+    /// to layer configurations (for example, environment specific values over those
+    /// loaded from a file, over defaults). The `custom` and `log_levels_by_environment`
+    /// maps are merged key-by-key, with keys on `self` taking precedence.
+    ///
+    /// Fields which cannot be distinguished from an unset value are never replaced:
+    /// `log_level`, `scrub_fields` and the `bool` flags always have a value, while a
+    /// `max_frames` of `None` explicitly disables truncation.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    ///
+    /// let mut config = Configuration {
+    ///     environment: Some("production".to_string()),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// config.merge(&Configuration {
+    ///     access_token: Some("my_token".to_string()),
+    ///     environment: Some("development".to_string()),
+    ///     ..Default::default()
+    /// });
+    ///
+    /// assert_eq!(config.environment, Some("production".to_string()));
+    /// assert_eq!(config.access_token, Some("my_token".to_string()));
+    /// ```
+    pub fn merge(&mut self, other: &Configuration) {
+        macro_rules! merge_field {
+            ($field:ident) => {
+                if self.$field.is_none() {
+                    self.$field = other.$field.clone();
+                }
+            };
+        }
+
+        merge_field!(access_token);
+        merge_field!(environment);
+        merge_field!(host);
+        merge_field!(code_version);
+        merge_field!(platform);
+        merge_field!(framework);
+        merge_field!(context);
+        merge_field!(context_fn);
+        merge_field!(fingerprint_fn);
+        merge_field!(message_prefix);
+        merge_field!(allowed_custom_keys);
+        merge_field!(capture_env);
+        merge_field!(max_events_per_window);
+        merge_field!(level_mapper);
+        merge_field!(on_drop);
+        merge_field!(panic_coalesce_window);
+
+        if let Some(other_custom) = &other.custom {
+            let custom = self.custom.get_or_insert_with(HashMap::new);
+            for (key, value) in other_custom {
+                custom.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+
+        for (environment, level) in &other.log_levels_by_environment {
+            self.log_levels_by_environment.entry(environment.clone()).or_insert_with(|| level.clone());
+        }
+    }
+
+    /// Gets the minimum level at which events in the provided environment will be
+    /// reported, falling back to the `log_level` if there is no override for it.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    ///
+    /// let mut config = Configuration::default();
+    /// config.log_levels_by_environment.insert("production".to_string(), Level::Warning);
+    ///
+    /// assert_eq!(config.log_level_for(Some("production")), Level::Warning);
+    /// assert_eq!(config.log_level_for(Some("development")), Level::Info);
+    /// ```
+    pub fn log_level_for(&self, environment: Option<&str>) -> crate::types::Level {
+        environment
+            .and_then(|environment| self.log_levels_by_environment.get(environment))
+            .unwrap_or(&self.log_level)
+            .clone()
+    }
+
+    /// Determines whether the field with the provided name is sensitive, based on
+    /// the configured `scrub_fields`.
+    pub fn is_sensitive_field(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.scrub_fields.iter().any(|field| name.contains(&field.to_lowercase()))
+    }
+
+    /// Records that the provided event was dropped, invoking the `on_drop` hook (if
+    /// one is configured) and producing the corresponding outcome.
+    pub (in crate) fn dropped(&self, data: &crate::types::Data, reason: crate::DropReason) -> crate::ReportOutcome {
+        if let Some(on_drop) = &self.on_drop {
+            on_drop(&crate::models::without_extra_data(data), reason);
+        }
+
+        crate::ReportOutcome::Dropped(reason)
+    }
+
+    /// Gets the lowest level at which events may be reported in any environment.
+    pub (in crate) fn min_log_level(&self) -> crate::types::Level {
+        self.log_levels_by_environment.values()
+            .fold(self.log_level.clone(), |min, level| if *level < min { level.clone() } else { min })
+    }
+
+    /// Creates a configuration, and a transport configuration, from a DSN-style
+    /// string of the form `rollbar://<token>@<host>/`.
+    ///
+    /// The access token is taken from the DSN's user info, while its host (and path)
+    /// determine the Rollbar API which events are sent to. The `rollbar://` and
+    /// `rollbar+https://` schemes connect using HTTPS, while `rollbar+http://` may
+    /// be used to connect to a local Rollbar-compatible instance using plain HTTP.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    ///
+    /// let (config, transport_config) = Configuration::from_dsn("rollbar://my_token@api.rollbar.com/").unwrap();
+    /// assert_eq!(config.access_token, Some("my_token".to_string()));
+    /// assert_eq!(transport_config.endpoint, "https://api.rollbar.com/api/1/item/");
+    /// ```
+    pub fn from_dsn(dsn: &str) -> Result<(Configuration, crate::TransportConfig), crate::Error> {
+        let url = reqwest::Url::parse(dsn).map_err(|e| crate::errors::user_with_internal(
+            "The Rollbar DSN you provided could not be parsed.",
+            "Make sure that your DSN is in the form rollbar://<token>@<host>/ and try again.",
+            e
+        ))?;
+
+        let scheme = match url.scheme() {
+            "rollbar" | "rollbar+https" => "https",
+            "rollbar+http" => "http",
+            other => return Err(crate::errors::user(
+                &format!("The Rollbar DSN you provided uses an unsupported scheme ({}).", other),
+                "Make sure that your DSN starts with rollbar://, rollbar+https:// or rollbar+http:// and try again.",
+            )),
+        };
+
+        if url.username().is_empty() {
+            return Err(crate::errors::user(
+                "The Rollbar DSN you provided does not include an access token.",
+                "Make sure that your DSN includes your access token in the form rollbar://<token>@<host>/ and try again.",
+            ));
+        }
+
+        let host = match url.host_str() {
+            Some(host) if !host.is_empty() => host,
+            _ => return Err(crate::errors::user(
+                "The Rollbar DSN you provided does not include a host.",
+                "Make sure that your DSN includes the host of the Rollbar API in the form rollbar://<token>@<host>/ and try again.",
+            )),
+        };
+
+        let port = url.port().map(|port| format!(":{}", port)).unwrap_or_default();
+        let base_url = format!("{}://{}{}{}", scheme, host, port, url.path());
+
+        let config = Configuration {
+            access_token: Some(url.username().to_string()),
+            ..Default::default()
+        };
+
+        Ok((config, crate::TransportConfig::from_base_url(&base_url)))
+    }
+
+    /// Validates that this configuration can be used to report events to Rollbar.
+    ///
+    /// Events reported without an access token are silently discarded, so you may
+    /// call this method during startup to fail fast if Rollbar has not been
+    /// configured correctly.
+    pub fn validate(&self) -> Result<(), crate::Error> {
+        match &self.access_token {
+            Some(token) if !token.trim().is_empty() => Ok(()),
+            _ => Err(crate::errors::user(
+                "No Rollbar access token has been configured, so events will not be reported.",
+                "Make sure that you have provided your Rollbar project's access token in your configuration and try again.",
+            )),
+        }
+    }
+
+    /// Serializes this configuration to JSON with the access token redacted.
+    ///
+    /// This is intended for use in diagnostics endpoints or logs, where you wish
+    /// to expose the effective configuration without leaking your access token.
+    /// If an access token has been set, it is replaced with `"***"`.
+    pub fn to_redacted_json(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+
+        if let Some(access_token) = value.get_mut("access_token") {
+            if !access_token.is_null() {
+                *access_token = serde_json::Value::String("***".to_string());
+            }
+        }
+
+        value
+    }
+
+    /// Sets the `code_version` to the git commit which your application was built from.
+    ///
+    /// The commit is read from the `GIT_COMMIT` environment variable at build time when
+    /// the `git-version` feature is enabled, so it must be set by your build (for example
+    /// `GIT_COMMIT=$(git rev-parse HEAD) cargo build`). If it was not set, the
+    /// `code_version` is left unchanged.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    ///
+    /// let config = Configuration::default().with_git_version();
+    /// ```
+    #[cfg(feature = "git-version")]
+    pub fn with_git_version(mut self) -> Self {
+        if let Some(commit) = option_env!("ROLLBAR_GIT_COMMIT") {
+            self.code_version = Some(commit.to_string());
+        }
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let mut config = Configuration {
+            environment: Some("production".to_string()),
+            platform: None,
+            custom: Some(crate::map!{ service: "api", owner: "team-a" }),
+            ..Default::default()
+        };
+
+        config.merge(&Configuration {
+            access_token: Some("my_token".to_string()),
+            environment: Some("development".to_string()),
+            platform: Some("linux".to_string()),
+            log_level: crate::types::Level::Debug,
+            custom: Some(crate::map!{ owner: "team-b", region: "eu" }),
+            ..Default::default()
+        });
+
+        assert_eq!(config.access_token, Some("my_token".to_string()));
+        assert_eq!(config.environment, Some("production".to_string()));
+        assert_eq!(config.platform, Some("linux".to_string()));
+        assert_eq!(config.log_level, crate::types::Level::Info);
+        assert_eq!(config.custom, Some(crate::map!{ service: "api", owner: "team-a", region: "eu" }));
+    }
+
+    #[test]
+    fn test_log_level_for() {
+        let mut config = Configuration {
+            log_level: crate::types::Level::Info,
+            ..Default::default()
+        };
+
+        config.log_levels_by_environment.insert("production".to_string(), crate::types::Level::Warning);
+        config.log_levels_by_environment.insert("staging".to_string(), crate::types::Level::Debug);
+
+        assert_eq!(config.log_level_for(Some("production")), crate::types::Level::Warning);
+        assert_eq!(config.log_level_for(Some("staging")), crate::types::Level::Debug);
+        assert_eq!(config.log_level_for(Some("development")), crate::types::Level::Info);
+        assert_eq!(config.log_level_for(None), crate::types::Level::Info);
+        assert_eq!(config.min_log_level(), crate::types::Level::Debug);
+    }
+
+    #[test]
+    fn test_is_sensitive_field() {
+        let config = Configuration::default();
+        assert!(config.is_sensitive_field("AWS_SECRET_ACCESS_KEY"));
+        assert!(config.is_sensitive_field("DATABASE_PASSWORD"));
+        assert!(config.is_sensitive_field("ROLLBAR_TOKEN"));
+        assert!(!config.is_sensitive_field("RUST_LOG"));
+
+        let config = Configuration { scrub_fields: vec!["rust".to_string()], ..Default::default() };
+        assert!(config.is_sensitive_field("RUST_LOG"));
+    }
+
+    #[test]
+    fn test_from_dsn() {
+        let (config, transport_config) = Configuration::from_dsn("rollbar://my_token@api.rollbar.com/").unwrap();
+        assert_eq!(config.access_token, Some("my_token".to_string()));
+        assert_eq!(transport_config.endpoint, "https://api.rollbar.com/api/1/item/");
+
+        let (config, transport_config) = Configuration::from_dsn("rollbar+http://my_token@localhost:8080/rollbar").unwrap();
+        assert_eq!(config.access_token, Some("my_token".to_string()));
+        assert_eq!(transport_config.endpoint, "http://localhost:8080/rollbar/api/1/item/");
+    }
+
+    #[test]
+    fn test_from_dsn_malformed() {
+        assert!(Configuration::from_dsn("not a dsn").is_err(), "unparseable DSNs should be rejected");
+        assert!(Configuration::from_dsn("https://my_token@api.rollbar.com/").unwrap_err().to_string().contains("unsupported scheme"));
+        assert!(Configuration::from_dsn("rollbar://api.rollbar.com/").unwrap_err().to_string().contains("access token"));
+        assert!(Configuration::from_dsn("rollbar://my_token@/").is_err(), "DSNs without a host should be rejected");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Configuration::default().validate().is_err(), "a missing token should fail validation");
+        assert!(Configuration { access_token: Some("".to_string()), ..Default::default() }.validate().is_err(), "an empty token should fail validation");
+        assert!(Configuration { access_token: Some("my_token".to_string()), ..Default::default() }.validate().is_ok());
+    }
+
+    #[test]
+    fn test_to_redacted_json() {
+        let config = Configuration {
+            access_token: Some("super_secret_token".to_string()),
+            environment: Some("production".to_string()),
+            log_level: crate::types::Level::Warning,
+            ..Default::default()
+        };
+
+        let json = config.to_redacted_json();
+        assert_eq!(json["access_token"], "***");
+        assert_eq!(json["environment"], "production");
+        assert_eq!(json["log_level"], serde_json::to_value(crate::types::Level::Warning).unwrap());
+        assert!(!json.to_string().contains("super_secret_token"));
+    }
+
+    #[test]
+    #[cfg(feature = "git-version")]
+    fn test_with_git_version() {
+        let config = Configuration::default().with_git_version();
+
+        // The commit is only available when `GIT_COMMIT` is set at build time (as it is in CI).
+        if let Some(commit) = config.code_version.as_deref() {
+            assert!(commit.len() == 7 || commit.len() == 40, "the commit should be a short or full hash, got {:?}", commit);
+            assert!(commit.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')), "the commit should be a lowercase hex string, got {:?}", commit);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use crate::types::{Body, CrashReport, Data, Level, Notifier};
+
+/// A builder for events which report a native crash using Rollbar's `crash_report`
+/// body, such as the output of a crash handler which produced a minidump.
+///
+/// Crash reports are reported at the `Critical` level by default.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let data = CrashReportBuilder::new("Thread 0 crashed with SIGSEGV at 0x0000000000000000")
+///     .minidump("https://dumps.example.com/5b1f0c2e.dmp")
+///     .build();
+///
+/// report(data);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CrashReportBuilder {
+    raw: String,
+    level: Level,
+    minidump: Option<String>,
+}
+
+impl CrashReportBuilder {
+    /// Creates a new crash report from the raw text of the crash.
+    pub fn new<S: Into<String>>(raw: S) -> Self {
+        Self {
+            raw: raw.into(),
+            level: Level::Critical,
+            minidump: None,
+        }
+    }
+
+    /// Sets the level at which the crash is reported.
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Sets a reference to the minidump produced by the crash (like the URL or path at
+    /// which it was stored), which is recorded in the event's `custom.minidump` field.
+    pub fn minidump<S: Into<String>>(mut self, reference: S) -> Self {
+        self.minidump = Some(reference.into());
+        self
+    }
+
+    /// Builds the event describing this crash report.
+    pub fn build(self) -> Data {
+        Data {
+            body: Body::CrashReportBody {
+                telemetry: None,
+                crash_report: CrashReport { raw: self.raw },
+            },
+            level: Some(self.level),
+            custom: self.minidump.map(|minidump| crate::map!{ minidump: minidump }),
+            notifier: Some(Notifier {
+                name: Some("SierraSoftworks/rollbar-rs".into()),
+                version: Some(crate::VERSION.into()),
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<CrashReportBuilder> for Data {
+    fn from(builder: CrashReportBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crash_report() {
+        let data = CrashReportBuilder::new("Thread 0 crashed with SIGSEGV")
+            .minidump("https://dumps.example.com/5b1f0c2e.dmp")
+            .build();
+
+        assert_eq!(data.level, Some(Level::Critical));
+        assert_eq!(data.custom.as_ref().map(|c| c["minidump"].clone()), Some(serde_json::json!("https://dumps.example.com/5b1f0c2e.dmp")));
+
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value["body"]["crash_report"]["raw"], "Thread 0 crashed with SIGSEGV");
+        assert!(value["body"].get("trace").is_none());
+        assert!(value["body"].get("message").is_none());
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+/// A builder for the `custom` data attached to an event, which provides typed
+/// setters for its fields as an alternative to the [`crate::map!`] macro.
+/// 
+/// This is particularly useful when custom data is constructed programmatically,
+/// or when it contains nested objects.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// let custom = CustomData::new()
+///     .string("owner", "billing")
+///     .int("attempt", 3)
+///     .bool("retrying", true)
+///     .object("order", CustomData::new()
+///         .string("id", "ord_123")
+///         .float("total", 49.95));
+/// 
+/// rollbar!(Error message = "Payment declined", custom = custom);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CustomData {
+    fields: HashMap<String, Value>,
+}
+
+impl CustomData {
+    /// Creates a new, empty, set of custom data.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a string field.
+    pub fn string<K: Into<String>, V: Into<String>>(self, key: K, value: V) -> Self {
+        self.json(key, Value::String(value.into()))
+    }
+
+    /// Sets an integer field.
+    pub fn int<K: Into<String>>(self, key: K, value: i64) -> Self {
+        self.json(key, value.into())
+    }
+
+    /// Sets a floating point field. Values which cannot be represented in JSON
+    /// (like `NaN` or infinity) are stored as `null`.
+    pub fn float<K: Into<String>>(self, key: K, value: f64) -> Self {
+        self.json(key, value.into())
+    }
+
+    /// Sets a boolean field.
+    pub fn bool<K: Into<String>>(self, key: K, value: bool) -> Self {
+        self.json(key, value.into())
+    }
+
+    /// Sets a field containing a nested object.
+    pub fn object<K: Into<String>>(self, key: K, value: CustomData) -> Self {
+        self.json(key, Value::Object(value.fields.into_iter().collect()))
+    }
+
+    /// Sets a field to an arbitrary JSON value.
+    pub fn json<K: Into<String>>(mut self, key: K, value: Value) -> Self {
+        self.fields.insert(key.into(), value);
+        self
+    }
+
+    /// Builds the map of fields which is expected by the `custom` field of an event.
+    pub fn build(self) -> HashMap<String, Value> {
+        self.fields
+    }
+}
+
+impl From<CustomData> for HashMap<String, Value> {
+    fn from(custom: CustomData) -> Self {
+        custom.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_data() {
+        let custom = CustomData::new()
+            .string("owner", "billing")
+            .int("attempt", 3)
+            .float("ratio", 0.5)
+            .bool("retrying", true)
+            .json("tags", serde_json::json!(["payments", "eu"]))
+            .object("order", CustomData::new()
+                .string("id", "ord_123")
+                .object("customer", CustomData::new().int("id", 42)));
+
+        assert_eq!(serde_json::to_value(custom.build()).unwrap(), serde_json::json!({
+            "owner": "billing",
+            "attempt": 3,
+            "ratio": 0.5,
+            "retrying": true,
+            "tags": ["payments", "eu"],
+            "order": {
+                "id": "ord_123",
+                "customer": { "id": 42 },
+            },
+        }));
+    }
+
+    #[test]
+    fn test_custom_data_in_event() {
+        let data = crate::rollbar_format!(message = "Payment declined", custom = CustomData::new().string("owner", "billing"));
+        assert_eq!(data.custom, Some(crate::map!{ owner: "billing" }));
+    }
+}
@@ -0,0 +1,592 @@
+use crate::types::{Body, Data, Exception, Frame, Level, Person, Request};
+
+/// Provides conversions between Rollbar's `Level` and the levels used by the
+/// `log` crate.
+///
+/// These conversions are intended to simplify integrating Rollbar with
+/// existing logging infrastructure, and are exposed as an extension trait
+/// since `Level` is defined outside of this crate.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// assert_eq!(Level::from_log(log::Level::Warn), Level::Warning);
+/// assert_eq!(Level::Error.to_log_filter(), log::LevelFilter::Error);
+/// ```
+pub trait LevelExt {
+    /// Converts a `log::Level` into the closest matching Rollbar `Level`.
+    ///
+    /// Since Rollbar has no equivalent to `log::Level::Trace`, trace
+    /// messages are mapped to `Level::Debug`.
+    fn from_log(level: log::Level) -> Self;
+
+    /// Converts this Rollbar `Level` into the `log::LevelFilter` which will
+    /// allow messages of this severity (and higher) through.
+    ///
+    /// Since the `log` crate has no equivalent to `Level::Critical`, critical
+    /// events are mapped to `log::LevelFilter::Error`.
+    fn to_log_filter(&self) -> log::LevelFilter;
+}
+
+impl LevelExt for Level {
+    fn from_log(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => Level::Error,
+            log::Level::Warn => Level::Warning,
+            log::Level::Info => Level::Info,
+            log::Level::Debug => Level::Debug,
+            log::Level::Trace => Level::Debug,
+        }
+    }
+
+    fn to_log_filter(&self) -> log::LevelFilter {
+        match self {
+            Level::Critical => log::LevelFilter::Error,
+            Level::Error => log::LevelFilter::Error,
+            Level::Warning => log::LevelFilter::Warn,
+            Level::Info => log::LevelFilter::Info,
+            Level::Debug => log::LevelFilter::Debug,
+        }
+    }
+}
+
+/// Provides a builder-style API for constructing `Frame` objects by hand.
+///
+/// This is intended for cases where you are building a trace from information
+/// which was not gathered from a Rust backtrace, such as parsed log files, FFI
+/// boundaries or other languages.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let frame = types::Frame::new("src/main.rs", 42)
+///     .method("main")
+///     .colno(5)
+///     .code("do_something()?;");
+///
+/// assert_eq!(frame.lineno, Some(42));
+/// ```
+pub trait FrameExt {
+    /// Creates a new `Frame` for the provided file and line number.
+    fn new<S: Into<String>>(filename: S, lineno: u32) -> Self;
+
+    /// Sets the name of the method which this frame represents.
+    fn method<S: Into<String>>(self, method: S) -> Self;
+
+    /// Sets the column number within the line which this frame represents.
+    fn colno(self, colno: u32) -> Self;
+
+    /// Sets the line of code which this frame represents.
+    fn code<S: Into<String>>(self, code: S) -> Self;
+
+    /// Sets a snapshot of the local variables which are relevant to this frame.
+    fn locals(self, locals: std::collections::HashMap<String, serde_json::Value>) -> Self;
+}
+
+impl FrameExt for Frame {
+    fn new<S: Into<String>>(filename: S, lineno: u32) -> Self {
+        Frame {
+            filename: filename.into(),
+            lineno: Some(lineno as i32),
+            ..Default::default()
+        }
+    }
+
+    fn method<S: Into<String>>(mut self, method: S) -> Self {
+        self.method = Some(method.into());
+        self
+    }
+
+    fn colno(mut self, colno: u32) -> Self {
+        self.colno = Some(colno as i32);
+        self
+    }
+
+    fn code<S: Into<String>>(mut self, code: S) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    fn locals(mut self, locals: std::collections::HashMap<String, serde_json::Value>) -> Self {
+        self.locals = Some(locals);
+        self
+    }
+}
+
+/// Provides a builder-style API for constructing the `Person` affected by an event.
+///
+/// Rollbar requires that every person has an `id`, while their `username` and
+/// `email` are optional.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let person = Person::new("12345")
+///     .username("jdoe")
+///     .email("jdoe@example.com");
+///
+/// rollbar!(message = "User signed in", person = person);
+/// ```
+pub trait PersonExt {
+    /// Creates a new `Person` with the provided identifier.
+    fn new<S: Into<String>>(id: S) -> Self;
+
+    /// Sets the username of this person.
+    fn username<S: Into<String>>(self, username: S) -> Self;
+
+    /// Sets the email address of this person.
+    fn email<S: Into<String>>(self, email: S) -> Self;
+}
+
+impl PersonExt for Person {
+    fn new<S: Into<String>>(id: S) -> Self {
+        Person {
+            id: id.into(),
+            username: None,
+            email: None,
+        }
+    }
+
+    fn username<S: Into<String>>(mut self, username: S) -> Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    fn email<S: Into<String>>(mut self, email: S) -> Self {
+        self.email = Some(email.into());
+        self
+    }
+}
+
+/// Provides a builder-style API for constructing the HTTP `Request` which
+/// triggered an event.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let request = Request::new("POST", "https://example.com/api/orders")
+///     .body(br#"{"item": "widget", "quantity": 3}"#, 1024);
+///
+/// rollbar!(Error message = "Failed to create order", request = request);
+/// ```
+pub trait RequestExt {
+    /// Creates a new `Request` with the provided HTTP method and URL.
+    fn new<M: Into<String>, U: Into<String>>(method: M, url: U) -> Self;
+
+    /// Sets the body of this request to (at most) the first `max` bytes of the
+    /// provided body.
+    ///
+    /// The body is decoded as UTF-8, with any invalid sequences replaced, and an
+    /// ellipsis is included (within the `max` byte budget) if it was truncated.
+    /// This allows a snippet of large or binary bodies to be included in an event
+    /// without bloating its payload.
+    fn body(self, body: &[u8], max: usize) -> Self;
+}
+
+impl RequestExt for Request {
+    fn new<M: Into<String>, U: Into<String>>(method: M, url: U) -> Self {
+        Request {
+            method: Some(method.into()),
+            url: Some(url.into()),
+            ..Default::default()
+        }
+    }
+
+    fn body(mut self, body: &[u8], max: usize) -> Self {
+        // Decoding one byte beyond the cap ensures that truncated bodies are always
+        // longer than `max`, so that they receive a truncation marker.
+        let snippet = String::from_utf8_lossy(&body[..body.len().min(max.saturating_add(1))]);
+        self.body = Some(crate::helpers::truncate_str(&snippet, max));
+        self
+    }
+}
+
+/// The kind of body carried by a Rollbar event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyKind {
+    /// A plain message, such as those reported using `rollbar!(message = ...)`.
+    Message,
+    /// An exception with a trace (or chain of traces).
+    Trace,
+    /// A raw crash report.
+    CrashReport,
+}
+
+/// Provides convenient accessors for inspecting the body of a `Data` object,
+/// without needing to match on the full shape of the `Body` enum.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let data = rollbar_format!(message = "Something happened");
+/// assert_eq!(data.body_kind(), BodyKind::Message);
+/// assert_eq!(data.message_text(), Some("Something happened"));
+/// assert!(data.exception().is_none());
+/// ```
+pub trait DataExt {
+    /// Gets the kind of body carried by this event.
+    fn body_kind(&self) -> BodyKind;
+
+    /// Gets the text of this event's message, if it is a message.
+    fn message_text(&self) -> Option<&str>;
+
+    /// Gets the exception described by this event, if it is a trace. For a chain
+    /// of traces, the first (outermost) exception is returned.
+    fn exception(&self) -> Option<&Exception>;
+}
+
+impl DataExt for Data {
+    fn body_kind(&self) -> BodyKind {
+        match &self.body {
+            Body::MessageBody { .. } => BodyKind::Message,
+            Body::TraceBody { .. } | Body::TraceChainBody { .. } => BodyKind::Trace,
+            Body::CrashReportBody { .. } => BodyKind::CrashReport,
+        }
+    }
+
+    fn message_text(&self) -> Option<&str> {
+        match &self.body {
+            Body::MessageBody { message, .. } => Some(message.body.as_str()),
+            _ => None,
+        }
+    }
+
+    fn exception(&self) -> Option<&Exception> {
+        match &self.body {
+            Body::TraceBody { trace, .. } => Some(&trace.exception),
+            Body::TraceChainBody { trace_chain, .. } => trace_chain.first().map(|trace| &trace.exception),
+            _ => None,
+        }
+    }
+}
+
+/// Allows errors to be reported to Rollbar from within a `Result` chain.
+///
+/// This makes it possible to report an error and continue to propagate it
+/// using `?`, without needing to break apart your control flow.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// fn load_config() -> Result<String, std::io::Error> {
+///     let config = std::fs::read_to_string("/etc/my-app/config.toml").report_err(Level::Error)?;
+///     Ok(config)
+/// }
+/// ```
+pub trait ReportResultExt {
+    /// Reports the error (if there is one) to Rollbar using the global client at the
+    /// provided level, returning the original `Result` unchanged.
+    fn report_err(self, level: Level) -> Self;
+}
+
+impl<T, E: std::error::Error> ReportResultExt for Result<T, E> {
+    #[track_caller]
+    fn report_err(self, level: Level) -> Self {
+        if let Err(err) = &self {
+            let mut data = crate::helpers::get_error_data(err);
+            data.level = Some(level);
+            crate::report(data);
+        }
+
+        self
+    }
+}
+
+/// Allows unexpected `None` values to be reported to Rollbar.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let user = std::env::var("USER").ok().report_none(Level::Warning, "The USER environment variable was not set");
+/// ```
+pub trait OptionReportExt {
+    /// Reports the provided message to Rollbar using the global client if this
+    /// is `None`, returning the original `Option` unchanged.
+    fn report_none<S: Into<String>>(self, level: Level, message: S) -> Self;
+}
+
+impl<T> OptionReportExt for Option<T> {
+    #[inline]
+    fn report_none<S: Into<String>>(self, level: Level, message: S) -> Self {
+        if self.is_none() {
+            crate::report(none_data(level, message.into()));
+        }
+
+        self
+    }
+}
+
+/// Converts a value into an event which may be reported to Rollbar.
+///
+/// This is accepted by [`crate::Client::report`] and [`crate::Client::try_report`],
+/// allowing strings, errors and your own domain types to be reported directly
+/// without needing to use the [`crate::rollbar_format!`] macro.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// struct PaymentDeclined { user_id: u64 }
+///
+/// impl IntoReportData for PaymentDeclined {
+///     fn into_report_data(self) -> types::Data {
+///         rollbar_format!(Warning message = "Payment declined", { user_id: self.user_id })
+///     }
+/// }
+///
+/// let client = Client::with_default_transport(Configuration::default()).unwrap();
+/// client.report("Something happened");
+/// client.report(PaymentDeclined { user_id: 42 });
+/// ```
+pub trait IntoReportData {
+    /// Converts this value into an event.
+    fn into_report_data(self) -> Data;
+}
+
+impl IntoReportData for Data {
+    #[inline]
+    fn into_report_data(self) -> Data {
+        self
+    }
+}
+
+impl IntoReportData for &str {
+    fn into_report_data(self) -> Data {
+        crate::rollbar_format!(message = self)
+    }
+}
+
+impl IntoReportData for String {
+    fn into_report_data(self) -> Data {
+        crate::rollbar_format!(message = self)
+    }
+}
+
+impl IntoReportData for &(dyn std::error::Error + 'static) {
+    #[track_caller]
+    fn into_report_data(self) -> Data {
+        crate::helpers::get_dyn_error_data(self)
+    }
+}
+
+fn none_data(level: Level, message: String) -> crate::types::Data {
+    let mut data = crate::rollbar_format!(message = message);
+    data.level = Some(level);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_from_log() {
+        assert_eq!(Level::from_log(log::Level::Error), Level::Error);
+        assert_eq!(Level::from_log(log::Level::Warn), Level::Warning);
+        assert_eq!(Level::from_log(log::Level::Info), Level::Info);
+        assert_eq!(Level::from_log(log::Level::Debug), Level::Debug);
+        assert_eq!(Level::from_log(log::Level::Trace), Level::Debug);
+    }
+
+    #[test]
+    fn level_to_log_filter() {
+        assert_eq!(Level::Critical.to_log_filter(), log::LevelFilter::Error);
+        assert_eq!(Level::Error.to_log_filter(), log::LevelFilter::Error);
+        assert_eq!(Level::Warning.to_log_filter(), log::LevelFilter::Warn);
+        assert_eq!(Level::Info.to_log_filter(), log::LevelFilter::Info);
+        assert_eq!(Level::Debug.to_log_filter(), log::LevelFilter::Debug);
+    }
+
+    #[test]
+    fn frame_builder() {
+        let frame = Frame::new("src/main.rs", 42)
+            .method("main")
+            .colno(5)
+            .code("do_something()?;");
+
+        assert_eq!(frame.filename, "src/main.rs");
+        assert_eq!(frame.lineno, Some(42));
+        assert_eq!(frame.colno, Some(5));
+        assert_eq!(frame.method, Some("main".to_string()));
+        assert_eq!(frame.code, Some("do_something()?;".to_string()));
+
+        let frame = Frame::new("src/main.rs", 42).locals(crate::map!{ user_id: 42, retry: true });
+        let json = serde_json::to_value(&frame).unwrap();
+        assert_eq!(json["locals"]["user_id"], 42);
+        assert_eq!(json["locals"]["retry"], true);
+    }
+
+    #[test]
+    fn person_builder() {
+        let person = Person::new("12345").username("jdoe").email("jdoe@example.com");
+        let json = serde_json::to_value(&person).unwrap();
+        assert_eq!(json["id"], "12345");
+        assert_eq!(json["username"], "jdoe");
+        assert_eq!(json["email"], "jdoe@example.com");
+
+        let json = serde_json::to_value(Person::new("12345")).unwrap();
+        assert_eq!(json["id"], "12345");
+        assert!(json.get("username").map(|u| u.is_null()).unwrap_or(true));
+    }
+
+    #[test]
+    fn data_accessors() {
+        let data = crate::rollbar_format!(message = "Something happened");
+        assert_eq!(data.body_kind(), BodyKind::Message);
+        assert_eq!(data.message_text(), Some("Something happened"));
+        assert!(data.exception().is_none());
+
+        let err = std::io::Error::new(std::io::ErrorKind::NotFound, "file not found");
+        let data = crate::rollbar_format!(error = err);
+        assert_eq!(data.body_kind(), BodyKind::Trace);
+        assert_eq!(data.message_text(), None);
+        assert_eq!(data.exception().and_then(|e| e.message.as_deref()), Some("file not found"));
+
+        let data = Data {
+            body: Body::CrashReportBody {
+                telemetry: None,
+                crash_report: crate::types::CrashReport { raw: "crash".to_string() },
+            },
+            ..Default::default()
+        };
+        assert_eq!(data.body_kind(), BodyKind::CrashReport);
+        assert!(data.message_text().is_none());
+        assert!(data.exception().is_none());
+    }
+
+    #[test]
+    fn request_builder() {
+        let request = Request::new("POST", "https://example.com/api/orders")
+            .body(b"{\"item\": \"widget\"}", 1024);
+
+        assert_eq!(request.method, Some("POST".to_string()));
+        assert_eq!(request.url, Some("https://example.com/api/orders".to_string()));
+        assert_eq!(request.body, Some("{\"item\": \"widget\"}".to_string()));
+
+        let body = vec![b'a'; 1024 * 1024];
+        let request = Request::new("POST", "https://example.com/upload").body(&body, 64);
+        let snippet = request.body.unwrap();
+        assert_eq!(snippet.len(), 64, "the body should be truncated to the cap");
+        assert!(snippet.ends_with('…'), "truncated bodies should include a marker");
+
+        let request = Request::new("POST", "https://example.com/upload").body(&[0xff, 0xfe, b'o', b'k'], 1024);
+        assert_eq!(request.body, Some("\u{fffd}\u{fffd}ok".to_string()), "invalid UTF-8 should be replaced");
+    }
+
+    #[test]
+    fn report_err() {
+        let _lock = crate::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let transport = crate::transport::MockTransport::default();
+        crate::set_default_client(crate::Client::new(transport.clone(), crate::Configuration {
+            access_token: Some("test_token".to_string()),
+            ..Default::default()
+        }));
+
+        // Other tests may report events through the global client while the default client is
+        // registered, so only the events reported by this test are considered.
+        let id = crate::helpers::new_uuid();
+        let reported = || transport.items().iter()
+            .filter(|item| item.data.exception().and_then(|e| e.message.as_deref()).map(|m| m.contains(&id)).unwrap_or_default())
+            .count();
+
+        let ok: Result<u32, std::io::Error> = Ok(42);
+        assert_eq!(ok.report_err(Level::Error).unwrap(), 42);
+        assert_eq!(reported(), 0, "nothing should be reported for an Ok result");
+
+        let err: Result<u32, std::io::Error> = Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("file not found ({})", id)));
+        let err = err.report_err(Level::Error).unwrap_err();
+        crate::clear_default_client();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), format!("file not found ({})", id));
+        assert_eq!(reported(), 1, "the error should be reported");
+
+        let item = transport.items().into_iter()
+            .find(|item| item.data.exception().and_then(|e| e.message.as_deref()).map(|m| m.contains(&id)).unwrap_or_default())
+            .unwrap();
+        assert_eq!(item.data.body_kind(), BodyKind::Trace);
+        assert_eq!(item.data.level, Some(Level::Error));
+    }
+
+    #[test]
+    fn get_error_data() {
+        let err = std::io::Error::new(std::io::ErrorKind::NotFound, "file not found");
+        let data = crate::helpers::get_error_data(&err);
+
+        match data.body {
+            crate::types::Body::TraceBody { trace, .. } => {
+                assert_eq!(trace.exception.message, Some("file not found".to_string()));
+                assert_eq!(trace.frames.last().map(|f| f.filename.as_str()), Some(file!()));
+            },
+            _ => panic!("Expected trace body")
+        }
+    }
+
+    #[test]
+    fn into_report_data() {
+        struct PaymentDeclined {
+            user_id: u64,
+        }
+
+        impl IntoReportData for PaymentDeclined {
+            fn into_report_data(self) -> Data {
+                crate::rollbar_format!(Warning message = "Payment declined", { user_id: self.user_id })
+            }
+        }
+
+        let data = "Something happened".into_report_data();
+        assert_eq!(data.message_text(), Some("Something happened"));
+        assert!(data.notifier.is_some(), "the notifier should be populated");
+
+        let data = String::from("Something else happened").into_report_data();
+        assert_eq!(data.message_text(), Some("Something else happened"));
+
+        let data = PaymentDeclined { user_id: 42 }.into_report_data();
+        assert_eq!(data.level, Some(Level::Warning));
+        match data.body {
+            Body::MessageBody { message, .. } => {
+                assert_eq!(message.body, "Payment declined");
+                assert_eq!(message.extra.get("user_id"), Some(&serde_json::json!(42)));
+            },
+            _ => panic!("Expected message body")
+        }
+
+        let err = std::io::Error::new(std::io::ErrorKind::NotFound, "file not found");
+        let data = (&err as &(dyn std::error::Error + 'static)).into_report_data();
+        assert_eq!(data.body_kind(), BodyKind::Trace);
+    }
+
+    #[test]
+    fn report_none() {
+        let _lock = crate::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let transport = crate::transport::MockTransport::default();
+        crate::set_default_client(crate::Client::new(transport.clone(), crate::Configuration {
+            access_token: Some("test_token".to_string()),
+            ..Default::default()
+        }));
+
+        let message = format!("The value was missing ({})", crate::helpers::new_uuid());
+        let reported = || transport.items().into_iter()
+            .filter(|item| item.data.message_text() == Some(message.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(Some(42).report_none(Level::Warning, message.as_str()), Some(42));
+        assert!(reported().is_empty(), "nothing should be reported for Some");
+
+        assert_eq!(None::<u32>.report_none(Level::Warning, message.as_str()), None);
+        crate::clear_default_client();
+
+        let reported = reported();
+        assert_eq!(reported.len(), 1, "a message should be reported for None");
+        assert_eq!(reported[0].data.level, Some(Level::Warning));
+    }
+}
@@ -1,7 +1,13 @@
+/// The maximum size of the `Debug` representation of an error which is included
+/// in an event's `custom.error_debug` field.
+const MAX_ERROR_DEBUG_BYTES: usize = 16 * 1024;
+
 /// Gets a Rollbar exception object representing the provided `std::errors::Error`.
 ///
 /// This method is used to allow Rollbar to automatically capture information about
 /// the type of exception which was raised, as well as its message and description.
+/// The description is the message of the error's source (if it has one), while its
+/// full `Debug` representation is recorded separately by [`attach_error_debug`].
 ///
 /// It is intended to be called, primarily, by the trace!() macro and generally should
 /// not be called by an end user themselves.
@@ -12,7 +18,225 @@ pub fn get_exception<T>(err: &T) -> crate::types::Exception
     crate::types::Exception {
         class: std::any::type_name::<T>().to_owned(),
         message: Some(err.to_string()),
-        description: err.source().map_or_else(|| Some(format!("{:#?}", err)), |s| Some(format!("{:#?}", s))),
+        description: err.source().map(|source| source.to_string()),
+    }
+}
+
+/// Gets a Rollbar exception object representing the provided dynamically typed error.
+///
+/// Since the concrete type of a `dyn Error` is not known at compile time, its class
+/// is determined by attempting to downcast it to a range of common error types from
+/// the standard library, falling back to `dyn std::error::Error` if none match. This
+/// is used to report errors held in a `Box<dyn Error>`, for which [`get_exception`]
+/// would otherwise report the class as the box itself.
+pub fn get_dyn_exception(err: &(dyn std::error::Error + 'static)) -> crate::types::Exception {
+    macro_rules! dyn_class {
+        ($err:expr, $($ty:ty),+) => {
+            $(if $err.is::<$ty>() { std::any::type_name::<$ty>() } else)+ { "dyn std::error::Error" }
+        };
+    }
+
+    let class = dyn_class!(err,
+        std::io::Error,
+        std::fmt::Error,
+        std::num::ParseIntError,
+        std::num::ParseFloatError,
+        std::num::TryFromIntError,
+        std::str::ParseBoolError,
+        std::str::Utf8Error,
+        std::string::FromUtf8Error,
+        std::char::ParseCharError,
+        std::net::AddrParseError,
+        std::env::VarError,
+        std::time::SystemTimeError,
+        serde_json::Error,
+        crate::Error
+    );
+
+    crate::types::Exception {
+        class: class.to_owned(),
+        message: Some(err.to_string()),
+        description: err.source().map(|source| source.to_string()),
+    }
+}
+
+/// Builds a Rollbar trace event describing the provided error.
+///
+/// This captures the current thread's backtrace, followed by a frame for the
+/// caller's location, in the same way as the `error = ...` form of the
+/// [`crate::rollbar_format!`] macro.
+#[track_caller]
+pub fn get_error_data<T>(err: &T) -> crate::types::Data
+    where T: std::error::Error
+{
+    let mut data = trace_data(get_exception(err), std::panic::Location::caller());
+    attach_error_debug(&mut data, err);
+    data
+}
+
+/// Builds a Rollbar trace event describing the provided dynamically typed error,
+/// such as one held in a `Box<dyn Error + Send + Sync>`.
+///
+/// This behaves in the same way as [`get_error_data`], but uses [`get_dyn_exception`]
+/// to determine the error's class.
+#[track_caller]
+pub fn get_dyn_error_data(err: &(dyn std::error::Error + 'static)) -> crate::types::Data {
+    let mut data = trace_data(get_dyn_exception(err), std::panic::Location::caller());
+    attach_error_debug(&mut data, err);
+    data
+}
+
+/// Builds a Rollbar trace chain event describing the provided error and each of
+/// the errors in its `source()` chain.
+///
+/// The outermost error is listed first and includes the current thread's backtrace
+/// (followed by a frame for the caller's location), while the errors which caused it
+/// have no frames since Rust errors do not capture their own backtraces. This is
+/// used by the `error_chain = ...` form of the [`crate::rollbar_format!`] macro.
+#[track_caller]
+pub fn get_error_chain_data(err: &(dyn std::error::Error + 'static)) -> crate::types::Data {
+    let mut data = trace_data(get_dyn_exception(err), std::panic::Location::caller());
+    attach_error_debug(&mut data, err);
+
+    if let crate::types::Body::TraceBody { telemetry, trace } = data.body {
+        let mut trace_chain = vec![trace];
+        let mut source = err.source();
+        while let Some(cause) = source {
+            trace_chain.push(crate::types::Trace {
+                exception: get_dyn_exception(cause),
+                frames: vec![],
+            });
+
+            source = cause.source();
+        }
+
+        data.body = crate::types::Body::TraceChainBody { telemetry, trace_chain };
+    }
+
+    data
+}
+
+/// Records the full (pretty printed) `Debug` representation of the provided error
+/// in the event's `custom.error_debug` field, keeping the detailed dump available
+/// for investigation without cluttering the exception's description.
+///
+/// Representations larger than 16KiB are truncated to keep the payload within
+/// Rollbar's size limits.
+pub fn attach_error_debug<E: std::fmt::Debug + ?Sized>(data: &mut crate::types::Data, err: &E) {
+    let debug = truncate_str(&format!("{:#?}", err), MAX_ERROR_DEBUG_BYTES);
+    data.custom.get_or_insert_with(Default::default)
+        .insert("error_debug".to_string(), debug.into());
+}
+
+fn trace_data(exception: crate::types::Exception, location: &std::panic::Location) -> crate::types::Data {
+    let frames = vec![crate::types::Frame {
+        filename: location.file().to_string(),
+        lineno: Some(location.line() as i32),
+        colno: Some(location.column() as i32),
+        ..Default::default()
+    }];
+
+    let mut data = crate::types::Data {
+        body: crate::types::Body::TraceBody {
+            telemetry: None,
+            trace: crate::types::Trace {
+                exception,
+                frames,
+            }
+        },
+        notifier: Some(crate::types::Notifier {
+            name: Some("SierraSoftworks/rollbar-rs".into()),
+            version: Some(crate::VERSION.into()),
+        }),
+        ..Default::default()
+    };
+
+    attach_unresolved_backtrace(&mut data);
+    data
+}
+
+/// Links the provided event with the active OpenTelemetry span, stamping it with the
+/// span's `custom.trace_id` and `custom.span_id` and recording its exception (if any)
+/// as an `exception` event on the span. Events which are reported outside of a valid
+/// span are unchanged.
+#[cfg(feature = "otel")]
+pub (in crate) fn attach_otel_context(data: &mut crate::types::Data) {
+    use opentelemetry::trace::TraceContextExt;
+
+    let context = opentelemetry::Context::current();
+    let span = context.span();
+    let span_context = span.span_context();
+    if !span_context.is_valid() {
+        return;
+    }
+
+    let custom = data.custom.get_or_insert_with(Default::default);
+    custom.entry("trace_id".to_string()).or_insert_with(|| span_context.trace_id().to_string().into());
+    custom.entry("span_id".to_string()).or_insert_with(|| span_context.span_id().to_string().into());
+
+    let exception = match &data.body {
+        crate::types::Body::TraceBody { trace, .. } => Some(&trace.exception),
+        crate::types::Body::TraceChainBody { trace_chain, .. } => trace_chain.first().map(|trace| &trace.exception),
+        _ => None,
+    };
+
+    if let Some(exception) = exception {
+        let mut attributes = vec![opentelemetry::KeyValue::new("exception.type", exception.class.clone())];
+        if let Some(message) = &exception.message {
+            attributes.push(opentelemetry::KeyValue::new("exception.message", message.clone()));
+        }
+
+        span.add_event("exception", attributes);
+    }
+}
+
+/// Redacts the values of any query parameters in the provided URL whose names are
+/// sensitive (based on the configured `scrub_fields`), replacing them with `***`
+/// while leaving the rest of the URL intact.
+pub (in crate) fn redact_url_query(url: &str, config: &crate::Configuration) -> String {
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+
+    let mut redacted = match url.split_once('?') {
+        Some((path, query)) => {
+            let query: Vec<String> = query.split('&')
+                .map(|param| match param.split_once('=') {
+                    Some((name, _)) if config.is_sensitive_field(name) => format!("{}=***", name),
+                    _ => param.to_string(),
+                })
+                .collect();
+
+            format!("{}?{}", path, query.join("&"))
+        },
+        None => url.to_string(),
+    };
+
+    if let Some(fragment) = fragment {
+        redacted.push('#');
+        redacted.push_str(fragment);
+    }
+
+    redacted
+}
+
+/// Remaps the level of an error event using the configured `level_mapper`, based on
+/// the exception of its (outermost) trace. Events without a trace are unchanged.
+pub (in crate) fn map_level(data: &mut crate::types::Data, config: &crate::Configuration) {
+    let level_mapper = match &config.level_mapper {
+        Some(level_mapper) => level_mapper,
+        None => return,
+    };
+
+    let exception = match &data.body {
+        crate::types::Body::TraceBody { trace, .. } => Some(&trace.exception),
+        crate::types::Body::TraceChainBody { trace_chain, .. } => trace_chain.first().map(|trace| &trace.exception),
+        _ => None,
+    };
+
+    if let Some(level) = exception.and_then(|exception| level_mapper(exception)) {
+        data.level = Some(level);
     }
 }
 
@@ -21,11 +245,24 @@ pub fn get_exception<T>(err: &T) -> crate::types::Exception
 /// 
 /// This method is use internally by Rollbar to generate a unique identifier for
 /// events before they queued for sending to Rollbar, ensuring that transports which
-/// attempt to retry requests will not result in duplicate entries.
+/// attempt to retry requests will not result in duplicate entries. Events which are
+/// spooled and later replayed keep the identifier they were originally assigned.
 pub (in crate) fn new_uuid() -> String {
     rollbar_rust::Uuid::new().to_string()
 }
 
+/// Gets the current time as a Unix timestamp (in seconds), which is used to
+/// record when an event occurred.
+///
+/// Events are timestamped when they are reported, ensuring that events which are
+/// delivered late (for example, after being retried) reflect when they occurred.
+pub (in crate) fn now_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
 /// Gathers the current thread's backtrace and returns it for use in a Rollbar
 /// trace event.
 /// 
@@ -34,18 +271,991 @@ pub (in crate) fn new_uuid() -> String {
 /// crate.
 pub fn get_backtrace_frames() -> Vec<crate::types::Frame> {
     let backtrace = backtrace::Backtrace::new();
-    let mut frames: Vec<crate::types::Frame> = backtrace.frames().iter()
+    let frames: Vec<crate::types::Frame> = backtrace.frames().iter()
         .flat_map(|frames| frames.symbols())
-        .map(|symbol| crate::types::Frame {
-            filename: symbol.filename().map_or_else(|| "".to_owned(), |f| format!("{}", f.display())),
-            lineno: symbol.lineno().map(|l| l as i32),
-            colno: symbol.colno().map(|c| c as i32),
-            method: symbol.name().map(|n| format!("{}", n)),
-            ..Default::default()
-        }).collect();
+        .map(|symbol| symbol_frame(symbol.filename(), symbol.lineno(), symbol.colno(), symbol.name()))
+        .collect();
+
+    into_rollbar_order(frames, "helpers::get_backtrace_frames")
+}
 
-    // Remove the last frame, which is this function.
-    frames.truncate(frames.len().saturating_sub(1));
+/// Converts the frames of a backtrace (which are ordered with the most recent call
+/// first) into the order Rollbar expects, with the most recent call last.
+///
+/// The frames of the function which captured the backtrace (named by `capture_fn`),
+/// along with those of the `backtrace` crate which it called, are removed so that
+/// the caller is the last frame. If it cannot be found, all of the frames are kept.
+fn into_rollbar_order(mut frames: Vec<crate::types::Frame>, capture_fn: &str) -> Vec<crate::types::Frame> {
+    if let Some(index) = frames.iter().position(|frame| frame.method.as_deref().is_some_and(|method| method.contains(capture_fn))) {
+        frames.drain(..=index);
+    }
 
+    frames.reverse();
     frames
-}
\ No newline at end of file
+}
+
+fn symbol_frame(filename: Option<&std::path::Path>, lineno: Option<u32>, colno: Option<u32>, name: Option<backtrace::SymbolName>) -> crate::types::Frame {
+    crate::types::Frame {
+        filename: filename.map_or_else(|| "".to_owned(), |f| format!("{}", f.display())),
+        lineno: lineno.map(|l| l as i32),
+        colno: colno.map(|c| c as i32),
+        method: name.map(|n| format!("{}", n)),
+        ..Default::default()
+    }
+}
+
+/// The filename given to the frames of a backtrace which has been captured but not yet
+/// symbolicated, whose `method` holds the frame's instruction pointer.
+pub (in crate) const UNRESOLVED_FRAME: &str = "<unresolved>";
+
+#[cfg(test)]
+thread_local! {
+    pub (in crate) static SYMBOLICATIONS: std::cell::Cell<usize> = std::cell::Cell::new(0);
+}
+
+/// Captures the current thread's backtrace without symbolicating it, adding its frames
+/// before any frames already present on the provided event's (outermost) trace.
+///
+/// Symbolicating a backtrace is expensive, so the frames only record their instruction
+/// pointers until the event has passed all of the filters which might cause it to be
+/// dropped (like the log level or rate limit), at which point they are resolved (or
+/// discarded, if the client's configuration disables `capture_backtrace`). This is
+/// used by the `error = ...` form of the [`crate::rollbar_format!`] macro.
+pub fn attach_unresolved_backtrace(data: &mut crate::types::Data) {
+    let backtrace = backtrace::Backtrace::new_unresolved();
+    let mut frames: Vec<crate::types::Frame> = backtrace.frames().iter()
+        .rev()
+        .map(|frame| crate::types::Frame {
+            filename: UNRESOLVED_FRAME.to_string(),
+            method: Some(format!("{:#x}", frame.ip() as usize)),
+            ..Default::default()
+        })
+        .collect();
+
+    if let Some(trace) = outermost_trace(data) {
+        frames.append(&mut trace.frames);
+        trace.frames = frames;
+    }
+}
+
+/// Resolves the frames added to an event by [`attach_unresolved_backtrace`], replacing
+/// them with the symbolicated frames of the backtrace.
+pub (in crate) fn resolve_backtrace(data: &mut crate::types::Data) {
+    let trace = match outermost_trace(data) {
+        Some(trace) => trace,
+        None => return,
+    };
+
+    let unresolved = trace.frames.iter().take_while(|frame| frame.filename == UNRESOLVED_FRAME).count();
+    if unresolved == 0 {
+        return;
+    }
+
+    #[cfg(test)]
+    SYMBOLICATIONS.with(|count| count.set(count.get() + 1));
+
+    // The frames are resolved with the most recent call first, matching the order of
+    // the symbols reported for inlined functions.
+    let mut frames = Vec::new();
+    for frame in trace.frames.drain(..unresolved).rev() {
+        let ip = match frame.method.as_deref().and_then(|ip| usize::from_str_radix(ip.trim_start_matches("0x"), 16).ok()) {
+            Some(ip) => ip,
+            None => continue,
+        };
+
+        // Each instruction pointer is the return address of a call, so we step back
+        // into the call instruction itself, in the same way as `backtrace` does.
+        let address = ip.saturating_sub(1) as *mut std::ffi::c_void;
+        backtrace::resolve(address, |symbol| frames.push(symbol_frame(symbol.filename(), symbol.lineno(), symbol.colno(), symbol.name())));
+    }
+
+    let mut frames = into_rollbar_order(frames, "helpers::attach_unresolved_backtrace");
+    frames.append(&mut trace.frames);
+    trace.frames = frames;
+}
+
+/// Removes the frames added to an event by [`attach_unresolved_backtrace`] without
+/// resolving them, leaving only the frames which were already present on its trace.
+pub (in crate) fn discard_backtrace(data: &mut crate::types::Data) {
+    if let Some(trace) = outermost_trace(data) {
+        trace.frames.retain(|frame| frame.filename != UNRESOLVED_FRAME);
+    }
+}
+
+/// Gets the outermost trace of an event, if it has one.
+fn outermost_trace(data: &mut crate::types::Data) -> Option<&mut crate::types::Trace> {
+    match &mut data.body {
+        crate::types::Body::TraceBody { trace, .. } => Some(trace),
+        crate::types::Body::TraceChainBody { trace_chain, .. } => trace_chain.first_mut(),
+        _ => None,
+    }
+}
+
+thread_local! {
+    static IN_PANIC_HOOK: std::cell::Cell<bool> = std::cell::Cell::new(false);
+}
+
+/// Runs the provided closure, unless it is already running on the current thread,
+/// returning whether it was run.
+///
+/// This is used by the `handle_panics!` macro to ensure that a panic which occurs
+/// while we are reporting another panic (for example, a panic in a `Drop` impl
+/// during unwinding, or a failure within our own hook) does not cause us to
+/// recurse into the hook, or perform further serialization or network work in a
+/// degraded state.
+pub fn guard_panic_hook<F: FnOnce()>(f: F) -> bool {
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            IN_PANIC_HOOK.with(|flag| flag.set(false));
+        }
+    }
+
+    if IN_PANIC_HOOK.with(|flag| flag.replace(true)) {
+        return false;
+    }
+
+    let _reset = Reset;
+    f();
+    true
+}
+
+lazy_static::lazy_static! {
+    /// The panics which are currently being coalesced, keyed by their file, line and
+    /// message.
+    static ref COALESCED_PANICS: std::sync::Mutex<std::collections::HashMap<(String, u32, String), CoalescedPanic>> = Default::default();
+}
+
+/// The state of a panic which is being coalesced by [`coalesce_panic`].
+#[derive(Debug)]
+struct CoalescedPanic {
+    /// When the panic was last reported, starting its window.
+    reported_at: std::time::Instant,
+    /// The number of identical panics which have not been reported since then.
+    suppressed: u64,
+}
+
+/// Coalesces identical panics (with the same location and message) which occur on
+/// any thread within the configured `panic_coalesce_window`, returning whether the
+/// provided panic event should be reported.
+///
+/// The first panic is reported immediately, while the identical panics which occur
+/// within the window are counted and not reported. The next identical panic after
+/// the window has elapsed is reported with the number of panics it represents
+/// (including those which were suppressed) in its `custom.occurrences` field. This
+/// is used by the `handle_panics!` macro to prevent a panic storm (like every worker
+/// in a thread pool panicking after a bad deploy) from flooding Rollbar, and
+/// generally should not need to be called directly.
+pub fn coalesce_panic(data: &mut crate::types::Data, payload: &(dyn std::any::Any + Send), location: Option<&std::panic::Location>) -> bool {
+    let window = crate::read_config().panic_coalesce_window;
+    match window {
+        Some(window) => coalesce_panic_within(window, &crate::SystemClock, data, payload, location),
+        None => true,
+    }
+}
+
+fn coalesce_panic_within(window: std::time::Duration, clock: &dyn crate::Clock, data: &mut crate::types::Data, payload: &(dyn std::any::Any + Send), location: Option<&std::panic::Location>) -> bool {
+    let key = (
+        location.map(|l| l.file().to_string()).unwrap_or_default(),
+        location.map(|l| l.line()).unwrap_or_default(),
+        panic_message(payload).to_string(),
+    );
+
+    let now = clock.now();
+    let mut panics = COALESCED_PANICS.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Panics whose window has elapsed without any suppressed occurrences no longer
+    // need to be tracked.
+    panics.retain(|_, panic| panic.suppressed > 0 || now.duration_since(panic.reported_at) < window);
+
+    let suppressed = match panics.get_mut(&key) {
+        Some(panic) if now.duration_since(panic.reported_at) < window => {
+            panic.suppressed += 1;
+            return false;
+        },
+        Some(panic) => std::mem::take(&mut panic.suppressed),
+        None => 0,
+    };
+
+    panics.insert(key, CoalescedPanic { reported_at: now, suppressed: 0 });
+    if suppressed > 0 {
+        attach_occurrences(data, suppressed + 1);
+    }
+
+    true
+}
+
+/// Extracts the message from a panic's payload, which is either a `&str` or a `String`
+/// when produced by `panic!()`, falling back to a generic message for other payloads.
+pub (in crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(s) => *s,
+        None => match payload.downcast_ref::<String>() {
+            Some(s) => s.as_str(),
+            None => "Panic",
+        }
+    }
+}
+
+/// Builds a Rollbar trace event describing a panic from its payload and location.
+///
+/// The panic's message is extracted from `&str` and `String` payloads (which are
+/// produced by `panic!()`), falling back to a generic message for other payload
+/// types. This is used by the `handle_panics!` macro and [`crate::report_caught_panic`]
+/// and generally should not need to be called directly.
+pub fn get_panic_data(payload: &(dyn std::any::Any + Send), location: Option<&std::panic::Location>) -> crate::types::Data {
+    let message = panic_message(payload);
+
+    let frames = if let Some(location) = location {
+        vec![
+            crate::types::Frame {
+                filename: location.file().into(),
+                lineno: Some(location.line()).map(|l| l as i32),
+                colno: Some(location.column()).map(|c| c as i32),
+                ..Default::default()
+            },
+        ]
+    } else {
+        vec![]
+    };
+
+    crate::types::Data {
+        body: crate::types::Body::TraceBody {
+            telemetry: None,
+            trace: crate::types::Trace {
+                exception: crate::types::Exception {
+                    class: "<panic>".into(),
+                    message: Some(message.into()),
+                    description: Some(message.into()),
+                    ..Default::default()
+                },
+                frames,
+            }
+        },
+        level: Some(crate::Level::Critical),
+        notifier: Some(crate::types::Notifier {
+            name: Some("SierraSoftworks/rollbar-rs".into()),
+            version: Some(crate::VERSION.into()),
+        }),
+        ..Default::default()
+    }
+}
+
+/// Merges the provided fields into the event's `custom` data, replacing the values of
+/// any fields which are already present. This is used by the `custom = ...` form of
+/// the [`crate::rollbar_format!`] macro, ensuring that fields added by other options
+/// (like `grouping_key = ...`) are kept regardless of the order they are provided in.
+pub fn merge_custom<C: Into<std::collections::HashMap<String, serde_json::Value>>>(data: &mut crate::types::Data, custom: C) {
+    data.custom.get_or_insert_with(Default::default).extend(custom.into());
+}
+
+/// Records that the provided event represents `count` occurrences of the same
+/// problem in its `custom.occurrences` field, allowing applications which aggregate
+/// identical errors themselves to report them once rather than sending an event for
+/// each occurrence. This is used by the `occurrences = ...` form of the
+/// [`crate::rollbar_format!`] macro.
+pub fn attach_occurrences(data: &mut crate::types::Data, count: u64) {
+    data.custom.get_or_insert_with(Default::default)
+        .insert("occurrences".to_string(), count.into());
+}
+
+/// Attaches a coarse grouping key (for example, the service and category of an
+/// error) to the provided event's `custom.grouping_key` field, allowing dashboards
+/// to group events whose fingerprints differ.
+///
+/// If the event does not already have a fingerprint, the grouping key is also used
+/// as its fingerprint so that Rollbar groups these events into a single item. An
+/// explicit fingerprint is never replaced. This is used by the `grouping_key = ...`
+/// form of the [`crate::rollbar_format!`] macro.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let mut data = rollbar_format!(message = "Payment declined");
+/// helpers::attach_grouping_key(&mut data, "billing/payments");
+/// assert_eq!(data.fingerprint, Some("billing/payments".to_string()));
+/// ```
+pub fn attach_grouping_key<S: Into<String>>(data: &mut crate::types::Data, key: S) {
+    let key = key.into();
+    if data.fingerprint.is_none() {
+        data.fingerprint = Some(key.clone());
+    }
+
+    data.custom.get_or_insert_with(Default::default)
+        .insert("grouping_key".to_string(), key.into());
+}
+
+/// Attaches top-level fields which are not modelled by `Data` to the provided
+/// event, merging them with any which were previously attached.
+///
+/// These fields are merged into the event's `data` when it is serialized, which
+/// allows fields from newer versions of Rollbar's item schema to be reported.
+/// Fields which are also set on the event itself take precedence over these.
+/// This is used by the `extra_data = ...` form of the [`crate::rollbar_format!`]
+/// macro.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let mut data = rollbar_format!(message = "Using a newer schema field");
+/// helpers::attach_extra_data(&mut data, map!{ attributes: ["beta"] });
+/// ```
+pub fn attach_extra_data<I: IntoIterator<Item = (String, serde_json::Value)>>(data: &mut crate::types::Data, extra: I) {
+    let fields = data.custom.get_or_insert_with(Default::default)
+        .entry(crate::models::EXTRA_DATA_KEY.to_string())
+        .or_insert_with(|| serde_json::Value::Object(Default::default()));
+
+    if !fields.is_object() {
+        *fields = serde_json::Value::Object(Default::default());
+    }
+
+    if let serde_json::Value::Object(fields) = fields {
+        fields.extend(extra);
+    }
+}
+
+/// Records the location at which a message was reported in its `custom.source`
+/// field when the `message-source` feature is enabled.
+///
+/// This is called by the [`crate::rollbar_format!`] macro with the location of
+/// its caller, giving message events the same locational context that error
+/// events receive from their frames. It does nothing when the feature is disabled.
+#[allow(unused_variables)]
+pub fn attach_message_source(data: &mut crate::types::Data, file: &str, line: u32, module: &str) {
+    #[cfg(feature = "message-source")]
+    data.custom.get_or_insert_with(Default::default).insert("source".to_string(), serde_json::json!({
+        "file": file,
+        "line": line,
+        "module": module,
+    }));
+}
+
+#[cfg(feature = "source-context")]
+lazy_static::lazy_static! {
+    static ref SOURCE_CACHE: std::sync::Mutex<std::collections::HashMap<String, Option<std::sync::Arc<Vec<String>>>>> = Default::default();
+}
+
+/// Populates the `code` and `context` fields of the provided frames using the
+/// source files they reference.
+///
+/// Files are read at most once and cached for future reports. Frames which
+/// reference missing, unreadable or excessively large files are left unchanged.
+#[cfg(feature = "source-context")]
+pub fn populate_source_context(frames: &mut [crate::types::Frame]) {
+    const CONTEXT_LINES: usize = 3;
+    const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+    for frame in frames.iter_mut() {
+        let lineno = match frame.lineno {
+            Some(lineno) if lineno > 0 && !frame.filename.is_empty() => lineno as usize,
+            _ => continue,
+        };
+
+        let lines = {
+            let mut cache = SOURCE_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+            cache.entry(frame.filename.clone()).or_insert_with(|| {
+                std::fs::metadata(&frame.filename).ok()
+                    .filter(|m| m.is_file() && m.len() <= MAX_FILE_SIZE)
+                    .and_then(|_| std::fs::read_to_string(&frame.filename).ok())
+                    .map(|source| std::sync::Arc::new(source.lines().map(|l| l.to_string()).collect()))
+            }).clone()
+        };
+
+        if let Some(lines) = lines {
+            if lineno > lines.len() {
+                continue;
+            }
+
+            let index = lineno - 1;
+            frame.code = Some(lines[index].clone());
+            frame.context = Some(crate::types::FrameContext {
+                pre: Some(lines[index.saturating_sub(CONTEXT_LINES)..index].to_vec()),
+                post: Some(lines[index + 1..(index + 1 + CONTEXT_LINES).min(lines.len())].to_vec()),
+            });
+        }
+    }
+}
+
+/// Attaches a reference to an externally hosted artifact to the provided event.
+///
+/// Attachments are recorded in the `custom.attachments` array as objects with
+/// a `name` and `url`, allowing you to associate large diagnostics (like heap
+/// dumps or log files) with an event without including them in the payload.
+/// Uploading the artifact itself is your responsibility.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let mut data = rollbar_format!(Error message = "The worker crashed");
+/// helpers::attach_url(&mut data, "heap.dump", "https://storage.example.com/dumps/heap.dump");
+/// rollbar_rs::report(data);
+/// ```
+pub fn attach_url<N: Into<String>, U: Into<String>>(data: &mut crate::types::Data, name: N, url: U) {
+    let attachment = serde_json::json!({
+        "name": name.into(),
+        "url": url.into(),
+    });
+
+    let attachments = data.custom.get_or_insert_with(Default::default)
+        .entry("attachments".to_string())
+        .or_insert_with(|| serde_json::Value::Array(vec![]));
+
+    match attachments {
+        serde_json::Value::Array(attachments) => attachments.push(attachment),
+        other => *other = serde_json::Value::Array(vec![attachment]),
+    }
+}
+
+/// Attaches a snapshot of local variables to the top (most recent) frame of the
+/// provided error event.
+///
+/// Rust cannot capture the values of local variables automatically, however you
+/// may provide the values which are relevant at the point an error is reported
+/// so that they are displayed alongside the frame in Rollbar. Events which do not
+/// contain a trace are left unchanged.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let err = std::io::Error::new(std::io::ErrorKind::NotFound, "file not found");
+/// let mut data = rollbar_format!(Error error = err);
+/// helpers::attach_locals(&mut data, map!{ path: "/etc/my-app/config.toml", attempt: 3 });
+/// rollbar_rs::report(data);
+/// ```
+pub fn attach_locals(data: &mut crate::types::Data, locals: std::collections::HashMap<String, serde_json::Value>) {
+    let frame = match &mut data.body {
+        crate::types::Body::TraceBody { trace, .. } => trace.frames.last_mut(),
+        crate::types::Body::TraceChainBody { trace_chain, .. } => trace_chain.first_mut().and_then(|trace| trace.frames.last_mut()),
+        _ => None,
+    };
+
+    if let Some(frame) = frame {
+        frame.locals.get_or_insert_with(Default::default).extend(locals);
+    }
+}
+
+/// Computes a stable fingerprint for the provided trace, for use in grouping.
+///
+/// The fingerprint is derived from the exception's class and the method names of
+/// the most recent few application frames (ignoring frames from the standard library,
+/// the `backtrace` crate and this crate), which are the last frames of the trace. Line numbers, addresses and the symbol
+/// hashes generated by the compiler are excluded so that the fingerprint remains
+/// stable across builds.
+pub fn get_fingerprint(trace: &crate::types::Trace) -> String {
+    const MAX_FRAMES: usize = 5;
+    const IGNORED_PREFIXES: &[&str] = &["std::", "core::", "alloc::", "backtrace::", "rollbar_rs::", "<std::", "<core::", "<alloc::"];
+
+    let mut hash = fnv1a(0xcbf29ce484222325, trace.exception.class.as_bytes());
+
+    let methods = trace.frames.iter()
+        .rev()
+        .filter_map(|frame| frame.method.as_deref())
+        .map(strip_symbol_hash)
+        .filter(|method| !IGNORED_PREFIXES.iter().any(|prefix| method.starts_with(prefix)))
+        .take(MAX_FRAMES);
+
+    for method in methods {
+        hash = fnv1a(hash, b"\n");
+        hash = fnv1a(hash, method.as_bytes());
+    }
+
+    format!("{:016x}", hash)
+}
+
+/// Removes the trailing `::h0123456789abcdef` hash which the compiler appends
+/// to symbol names, since it changes between builds.
+fn strip_symbol_hash(method: &str) -> &str {
+    match method.rsplit_once("::h") {
+        Some((name, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => name,
+        _ => method,
+    }
+}
+
+/// Derives a uuid for the provided event from its content, such that identical
+/// events (even when reported by different processes) share the same uuid.
+///
+/// The uuid is derived from the event's level, environment and fingerprint, or
+/// its body if it does not have a fingerprint. Timestamps and other per-occurrence
+/// details are excluded, so that identical events are deduplicated by Rollbar.
+pub (in crate) fn content_uuid(data: &crate::types::Data) -> String {
+    let content = match &data.fingerprint {
+        Some(fingerprint) => fingerprint.clone(),
+        None => serde_json::to_string(&data.body).unwrap_or_default(),
+    };
+
+    let level = data.level.as_ref().and_then(|level| serde_json::to_string(level).ok()).unwrap_or_default();
+    let environment = data.environment.as_deref().unwrap_or_default();
+
+    let hash = |seed: u64| {
+        let hash = fnv1a(seed, level.as_bytes());
+        let hash = fnv1a(fnv1a(hash, b"\n"), environment.as_bytes());
+        fnv1a(fnv1a(hash, b"\n"), content.as_bytes())
+    };
+
+    let high = hash(0xcbf29ce484222325);
+    let low = hash(0x84222325cbf29ce4);
+
+    format!("{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff)
+}
+
+/// Computes the FNV-1a hash of the provided bytes, continuing from the given state.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+/// Detects the platform that the application is hosted on using well-known
+/// environment variables, returning `None` if it could not be determined.
+pub fn detect_platform() -> Option<String> {
+    detect_platform_from(|name| std::env::var(name).ok())
+}
+
+fn detect_platform_from<F: Fn(&str) -> Option<String>>(env: F) -> Option<String> {
+    const PLATFORMS: &[(&str, &str)] = &[
+        ("AWS_LAMBDA_FUNCTION_NAME", "aws-lambda"),
+        ("FUNCTIONS_WORKER_RUNTIME", "azure-functions"),
+        ("K_SERVICE", "google-cloud-run"),
+        ("ECS_CONTAINER_METADATA_URI_V4", "aws-ecs"),
+        ("KUBERNETES_SERVICE_HOST", "kubernetes"),
+        ("DYNO", "heroku"),
+    ];
+
+    PLATFORMS.iter()
+        .find(|(variable, _)| env(variable).map(|v| !v.is_empty()).unwrap_or_default())
+        .map(|(_, platform)| platform.to_string())
+}
+
+/// Determines whether the application appears to be running under a test harness,
+/// using the `NEXTEST` and `RUST_TEST_THREADS` environment variables.
+///
+/// Only tests run by `cargo nextest`, or by `cargo test` with an explicit number of
+/// threads (by setting `RUST_TEST_THREADS`), are detected; plain `cargo test` sets
+/// neither of these variables.
+///
+/// This is used by [`crate::handle_panics_unless_testing!`] to avoid reporting
+/// panics which are raised by failing tests.
+pub fn is_test_harness() -> bool {
+    is_test_harness_from(|name| std::env::var(name).ok())
+}
+
+fn is_test_harness_from<F: Fn(&str) -> Option<String>>(env: F) -> bool {
+    const MARKERS: &[&str] = &["RUST_TEST_THREADS", "NEXTEST"];
+
+    MARKERS.iter().any(|marker| env(marker).is_some())
+}
+
+/// Populates the `branch` and `code_version` of the provided server using the
+/// `GIT_BRANCH` and `GIT_COMMIT` environment variables. Values which have already
+/// been set on the server are never replaced.
+///
+/// The commit recorded at build time by the `git-version` feature is not used here;
+/// use [`crate::Configuration::with_git_version`] to report it as the `code_version`.
+pub fn populate_server_git_info(server: &mut crate::types::Server) {
+    populate_server_git_info_from(server, |name| std::env::var(name).ok())
+}
+
+fn populate_server_git_info_from<F: Fn(&str) -> Option<String>>(server: &mut crate::types::Server, env: F) {
+    let env = |name: &str| env(name).filter(|value| !value.is_empty());
+
+    if server.branch.is_none() {
+        server.branch = env("GIT_BRANCH");
+    }
+
+    if server.code_version.is_none() {
+        server.code_version = env("GIT_COMMIT");
+    }
+}
+
+/// Truncates the provided string to at most `max_bytes` bytes, appending an
+/// ellipsis to indicate that it has been shortened.
+///
+/// Truncation always occurs on a character boundary, ensuring that multi-byte
+/// UTF-8 characters are never split (which would otherwise produce invalid
+/// strings). The ellipsis is included within the `max_bytes` budget.
+pub fn truncate_str(s: &str, max_bytes: usize) -> String {
+    const ELLIPSIS: &str = "…";
+
+    if s.len() <= max_bytes {
+        return s.to_string();
+    }
+
+    let (budget, marker) = if max_bytes >= ELLIPSIS.len() {
+        (max_bytes - ELLIPSIS.len(), ELLIPSIS)
+    } else {
+        (max_bytes, "")
+    };
+
+    let mut end = budget;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}{}", &s[..end], marker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_debug() {
+        #[derive(Debug)]
+        struct OuterError(std::io::Error);
+
+        impl std::fmt::Display for OuterError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "We could not load the configuration.")
+            }
+        }
+
+        impl std::error::Error for OuterError {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let err = OuterError(std::io::Error::new(std::io::ErrorKind::NotFound, "file not found"));
+        let data = get_error_data(&err);
+
+        match &data.body {
+            crate::types::Body::TraceBody { trace, .. } => {
+                assert_eq!(trace.exception.message, Some("We could not load the configuration.".to_string()));
+                assert_eq!(trace.exception.description, Some("file not found".to_string()), "the description should be the source's message");
+            },
+            _ => panic!("Expected trace body")
+        }
+
+        let custom = data.custom.expect("the custom field should be populated");
+        let debug = custom["error_debug"].as_str().expect("the error's debug representation should be included");
+        assert_eq!(debug, format!("{:#?}", err));
+        assert!(debug.contains("NotFound"));
+
+        let mut data = crate::rollbar_format!(message = "Test message");
+        attach_error_debug(&mut data, &"x".repeat(MAX_ERROR_DEBUG_BYTES * 2));
+        assert!(data.custom.unwrap()["error_debug"].as_str().unwrap().len() <= MAX_ERROR_DEBUG_BYTES, "the debug representation should be truncated");
+    }
+
+    #[test]
+    fn test_is_test_harness() {
+        assert!(!is_test_harness_from(|_| None));
+        assert!(is_test_harness_from(|name| if name == "RUST_TEST_THREADS" { Some("1".to_string()) } else { None }));
+        assert!(is_test_harness_from(|name| if name == "NEXTEST" { Some("1".to_string()) } else { None }));
+        assert!(!is_test_harness_from(|name| if name == "CI" { Some("true".to_string()) } else { None }));
+    }
+
+    #[test]
+    fn test_is_test_harness_env() {
+        let _lock = crate::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let previous: Vec<(&str, Option<std::ffi::OsString>)> = ["RUST_TEST_THREADS", "NEXTEST"].into_iter()
+            .map(|name| (name, std::env::var_os(name)))
+            .collect();
+
+        std::env::remove_var("RUST_TEST_THREADS");
+        std::env::remove_var("NEXTEST");
+        let without_markers = is_test_harness();
+
+        std::env::set_var("NEXTEST", "1");
+        let with_nextest = is_test_harness();
+
+        std::env::remove_var("NEXTEST");
+        std::env::set_var("RUST_TEST_THREADS", "1");
+        let with_test_threads = is_test_harness();
+
+        for (name, value) in previous {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+
+        assert!(!without_markers, "plain `cargo test` should not be detected");
+        assert!(with_nextest, "`cargo nextest` should be detected");
+        assert!(with_test_threads, "`cargo test` with an explicit thread count should be detected");
+    }
+
+    #[test]
+    fn test_content_uuid() {
+        let first = content_uuid(&crate::rollbar_format!(Error message = "Deployment failed", environment = "production"));
+        let second = content_uuid(&crate::rollbar_format!(Error message = "Deployment failed", environment = "production"));
+        assert_eq!(first, second, "identical events should have the same uuid");
+        assert_eq!(first.len(), 36);
+
+        assert_ne!(first, content_uuid(&crate::rollbar_format!(Error message = "Deployment succeeded", environment = "production")));
+        assert_ne!(first, content_uuid(&crate::rollbar_format!(Error message = "Deployment failed", environment = "staging")));
+        assert_ne!(first, content_uuid(&crate::rollbar_format!(Warning message = "Deployment failed", environment = "production")));
+    }
+
+    #[test]
+    fn test_attach_locals() {
+        let err = std::io::Error::new(std::io::ErrorKind::NotFound, "file not found");
+        let mut data = crate::rollbar_format!(error = err);
+        attach_locals(&mut data, crate::map!{ path: "/etc/config.toml", attempt: 3 });
+
+        let json = serde_json::to_value(&data).unwrap();
+        let frames = json["body"]["trace"]["frames"].as_array().unwrap();
+        let locals = &frames.last().unwrap()["locals"];
+        assert_eq!(locals["path"], "/etc/config.toml");
+        assert_eq!(locals["attempt"], 3);
+
+        let mut data = crate::rollbar_format!(message = "Not an error");
+        attach_locals(&mut data, crate::map!{ path: "/etc/config.toml" });
+        assert!(!serde_json::to_string(&data).unwrap().contains("/etc/config.toml"), "messages should not be changed");
+    }
+
+    #[test]
+    fn test_populate_server_git_info() {
+        let env = |name: &str| match name {
+            "GIT_BRANCH" => Some("main".to_string()),
+            "GIT_COMMIT" => Some("abc123".to_string()),
+            _ => None,
+        };
+
+        let mut server = crate::types::Server {
+            host: Some("web-1".to_string()),
+            root: None,
+            branch: None,
+            code_version: None,
+        };
+
+        populate_server_git_info_from(&mut server, env);
+        assert_eq!(server.branch, Some("main".to_string()));
+        assert_eq!(server.code_version, Some("abc123".to_string()));
+
+        let mut server = crate::types::Server {
+            host: None,
+            root: None,
+            branch: Some("release".to_string()),
+            code_version: Some("def456".to_string()),
+        };
+
+        populate_server_git_info_from(&mut server, env);
+        assert_eq!(server.branch, Some("release".to_string()), "explicit values should not be replaced");
+        assert_eq!(server.code_version, Some("def456".to_string()));
+    }
+
+    #[test]
+    fn test_guard_panic_hook() {
+        let mut calls = 0;
+        let ran = guard_panic_hook(|| {
+            calls += 1;
+            assert!(!guard_panic_hook(|| panic!("the nested hook should not run")), "nested calls should be skipped");
+        });
+
+        assert!(ran);
+        assert_eq!(calls, 1);
+        assert!(guard_panic_hook(|| {}), "the guard should be released once the hook completes");
+    }
+
+    #[test]
+    fn test_get_dyn_exception() {
+        let err: Box<dyn std::error::Error + Send + Sync> = Box::new(std::io::Error::new(std::io::ErrorKind::NotFound, "file not found"));
+        let exception = get_dyn_exception(err.as_ref());
+        assert_eq!(exception.class, "std::io::error::Error");
+        assert_eq!(exception.message, Some("file not found".to_string()));
+
+        let err: Box<dyn std::error::Error> = "not a number".parse::<u32>().unwrap_err().into();
+        let exception = get_dyn_exception(err.as_ref());
+        assert_eq!(exception.class, "core::num::error::ParseIntError");
+
+        let err: Box<dyn std::error::Error> = "a custom error".into();
+        let exception = get_dyn_exception(err.as_ref());
+        assert!(!exception.class.is_empty());
+        assert_eq!(exception.message, Some("a custom error".to_string()));
+    }
+
+    #[test]
+    fn test_coalesce_panic() {
+        let clock = crate::MockClock::new();
+        let window = std::time::Duration::from_secs(5);
+        let location = std::panic::Location::caller();
+        let payload: Box<dyn std::any::Any + Send> = Box::new(format!("Worker crashed ({})", new_uuid()));
+
+        let coalesce = || {
+            let mut data = get_panic_data(payload.as_ref(), Some(location));
+            if coalesce_panic_within(window, &clock, &mut data, payload.as_ref(), Some(location)) {
+                Some(data)
+            } else {
+                None
+            }
+        };
+
+        let first = coalesce().expect("the first panic should be reported immediately");
+        assert!(first.custom.map(|c| !c.contains_key("occurrences")).unwrap_or(true));
+
+        for _ in 0..4 {
+            assert!(coalesce().is_none(), "identical panics within the window should not be reported");
+        }
+
+        clock.advance(window);
+        let next = coalesce().expect("the next panic after the window should be reported");
+        assert_eq!(next.custom.as_ref().map(|c| c["occurrences"].clone()), Some(serde_json::json!(5)), "the suppressed panics should be counted");
+        assert!(coalesce().is_none(), "a new window should start once a panic is reported");
+    }
+
+    #[test]
+    fn test_get_panic_data() {
+        let payload: Box<dyn std::any::Any + Send> = Box::new("Something went wrong".to_string());
+        let data = get_panic_data(payload.as_ref(), None);
+
+        match data.body {
+            crate::types::Body::TraceBody { trace, .. } => {
+                assert_eq!(trace.exception.class, "<panic>");
+                assert_eq!(trace.exception.message, Some("Something went wrong".to_string()));
+                assert!(trace.frames.is_empty());
+            },
+            _ => panic!("Expected trace body")
+        }
+
+        let payload: Box<dyn std::any::Any + Send> = Box::new(42);
+        let data = get_panic_data(payload.as_ref(), Some(std::panic::Location::caller()));
+
+        match data.body {
+            crate::types::Body::TraceBody { trace, .. } => {
+                assert_eq!(trace.exception.message, Some("Panic".to_string()));
+                assert_eq!(trace.frames.len(), 1);
+            },
+            _ => panic!("Expected trace body")
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "message-source")]
+    fn test_attach_message_source() {
+        let line = line!() + 1;
+        let data = crate::rollbar_format!(Info message = "Something happened", custom = crate::map!{ foo: "bar" });
+
+        let custom = data.custom.expect("the custom field should be populated");
+        assert_eq!(custom["foo"], "bar", "existing custom data should be preserved");
+        assert_eq!(custom["source"]["file"], file!(), "the source should reflect the caller's file");
+        assert_eq!(custom["source"]["line"], line);
+        assert_eq!(custom["source"]["module"], module_path!());
+    }
+
+    #[test]
+    #[cfg(not(feature = "message-source"))]
+    fn test_attach_message_source_disabled() {
+        let data = crate::rollbar_format!(Info message = "Something happened");
+        assert!(data.custom.is_none(), "the source should only be captured when the feature is enabled");
+    }
+
+    #[test]
+    #[cfg(feature = "source-context")]
+    fn test_populate_source_context() {
+        let mut frames = vec![
+            crate::types::Frame {
+                filename: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/source_context.txt").to_string(),
+                lineno: Some(5),
+                ..Default::default()
+            },
+            crate::types::Frame {
+                filename: "/this/file/does/not/exist.rs".to_string(),
+                lineno: Some(5),
+                ..Default::default()
+            },
+        ];
+
+        populate_source_context(&mut frames);
+
+        assert_eq!(frames[0].code, Some("line 5".to_string()));
+        let context = frames[0].context.as_ref().expect("the frame should have context");
+        assert_eq!(context.pre, Some(vec!["line 2".to_string(), "line 3".to_string(), "line 4".to_string()]));
+        assert_eq!(context.post, Some(vec!["line 6".to_string(), "line 7".to_string(), "line 8".to_string()]));
+
+        assert_eq!(frames[1].code, None);
+        assert!(frames[1].context.is_none());
+    }
+
+    #[test]
+    fn test_attach_url() {
+        let mut data = crate::rollbar_format!(message = "Test message");
+        attach_url(&mut data, "heap.dump", "https://example.com/heap.dump");
+        attach_url(&mut data, "app.log", "https://example.com/app.log");
+
+        assert_eq!(data.custom.unwrap()["attachments"], serde_json::json!([
+            { "name": "heap.dump", "url": "https://example.com/heap.dump" },
+            { "name": "app.log", "url": "https://example.com/app.log" },
+        ]));
+    }
+
+    #[test]
+    fn test_get_backtrace_frames() {
+        let frames = get_backtrace_frames();
+        let caller = frames.last().and_then(|frame| frame.method.as_deref()).unwrap_or_default();
+        assert!(caller.contains("test_get_backtrace_frames"), "the caller should be the most recent (last) frame, got {}", caller);
+        assert!(!frames.iter().any(|frame| frame.method.as_deref().is_some_and(|method| method.contains("helpers::get_backtrace_frames"))), "the capturing function should be removed");
+    }
+
+    #[test]
+    fn test_get_fingerprint() {
+        fn trace(class: &str, methods: &[&str]) -> crate::types::Trace {
+            crate::types::Trace {
+                exception: crate::types::Exception {
+                    class: class.to_string(),
+                    ..Default::default()
+                },
+                frames: methods.iter().enumerate().map(|(i, method)| crate::types::Frame {
+                    filename: "src/main.rs".to_string(),
+                    lineno: Some(i as i32),
+                    method: Some(method.to_string()),
+                    ..Default::default()
+                }).collect(),
+            }
+        }
+
+        let a = trace("std::io::Error", &["app::main::hfedcba9876543210", "app::load_config::h0123456789abcdef", "backtrace::capture::Backtrace::new::h0123456789abcdef"]);
+        let b = trace("std::io::Error", &["app::main::hbbbbbbbbbbbbbbbb", "app::load_config::haaaaaaaaaaaaaaaa"]);
+        let c = trace("std::io::Error", &["app::main::hfedcba9876543210", "app::save_config::h0123456789abcdef"]);
+        let d = trace("std::fmt::Error", &["app::main::hfedcba9876543210", "app::load_config::h0123456789abcdef"]);
+
+        assert_eq!(get_fingerprint(&a), get_fingerprint(&b), "the same application frames should produce the same fingerprint");
+        assert_ne!(get_fingerprint(&a), get_fingerprint(&c), "different frames should produce different fingerprints");
+        assert_ne!(get_fingerprint(&a), get_fingerprint(&d), "different exception classes should produce different fingerprints");
+
+        let deep = trace("std::io::Error", &["app::main", "app::run", "app::serve", "app::handle", "app::route", "app::load", "app::parse"]);
+        let shallow = trace("std::io::Error", &["std::rt::lang_start", "app::run", "app::serve", "app::handle", "app::route", "app::load", "app::parse"]);
+        assert_eq!(get_fingerprint(&deep), get_fingerprint(&shallow), "only the most recent application frames should be used");
+    }
+
+    #[test]
+    fn test_detect_platform() {
+        assert_eq!(detect_platform_from(|_| None), None);
+
+        assert_eq!(detect_platform_from(|name| match name {
+            "AWS_LAMBDA_FUNCTION_NAME" => Some("my-function".to_string()),
+            _ => None,
+        }), Some("aws-lambda".to_string()));
+
+        assert_eq!(detect_platform_from(|name| match name {
+            "KUBERNETES_SERVICE_HOST" => Some("10.0.0.1".to_string()),
+            _ => None,
+        }), Some("kubernetes".to_string()));
+    }
+
+    #[test]
+    fn test_truncate_str() {
+        assert_eq!(truncate_str("short", 10), "short");
+        assert_eq!(truncate_str("this is a long string", 10), "this is…");
+
+        let long = "日本語のエラーメッセージ".repeat(10);
+        for limit in 0..20 {
+            let truncated = truncate_str(&long, limit);
+            assert!(truncated.len() <= limit, "the truncated string should fit within {} bytes", limit);
+            assert!(std::str::from_utf8(truncated.as_bytes()).is_ok());
+        }
+    }
+}
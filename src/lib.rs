@@ -1,27 +1,142 @@
+//! # Features
+//! - `threaded` (default) sends events to Rollbar from a dedicated background thread.
+//! - `async` sends events to Rollbar using the current Tokio runtime.
+//! - `git-version` allows `Configuration::with_git_version` to report the commit recorded from the `GIT_COMMIT` environment variable at build time.
+//! - `source-context` includes the surrounding lines of source code in each frame of a reported backtrace.
+//! - `message-source` records the file, line and module from which messages are reported in their `custom.source` field.
+//! - `dangerous-tls` allows transports to skip verification of Rollbar's TLS certificate, for testing only.
+//! - `msgpack` allows transports to serialize events using MessagePack for Rollbar-compatible ingesters which accept it.
+//! - `otel` records reported errors as exception events on the active OpenTelemetry span, and stamps events with its `custom.trace_id` and `custom.span_id`.
+//! - `tracing` uses the name of the current `tracing` span as the `context` for events which do not have one, and adds the fields recorded by the `SpanFieldsLayer` to their `custom` data.
+//! - `test-util` provides a `MockClock` which allows time-based features (like rate limiting) to be tested deterministically.
+//! 
+//! The `threaded` and `async` transports may be enabled at the same time (for example,
+//! when Cargo's feature unification enables `threaded` through another crate in your
+//! workspace), in which case the global client will use the `async` transport.
+
 #[macro_use] extern crate log;
 extern crate serde;
 
+mod circuit_breaker;
 mod client;
+mod clock;
 mod configuration;
+mod crash_report;
+mod custom;
 mod errors;
+mod extensions;
 pub mod helpers;
 mod macros;
 mod models;
+mod outcome;
+mod rate_limiter;
+mod scope;
+#[cfg(feature = "tracing")]
+mod span_fields;
+mod spool;
 mod transport;
 
-use std::{sync::RwLock, collections::HashMap};
+use std::{sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, atomic::{AtomicBool, AtomicU8, Ordering}}, collections::HashMap};
 
-pub use client::Client;
+pub use client::{Client, HeartbeatGuard, Reporter};
+pub use clock::{Clock, SystemClock};
+#[cfg(any(test, feature = "test-util"))]
+pub use clock::MockClock;
 pub use configuration::Configuration;
+pub use crash_report::CrashReportBuilder;
+pub use custom::CustomData;
 pub use errors::Error;
+pub use outcome::{DropReason, ReportOutcome};
+pub use scope::*;
+#[cfg(feature = "tracing")]
+pub use span_fields::SpanFieldsLayer;
+pub use spool::Spool;
+pub use extensions::*;
 pub use transport::*;
 pub use rollbar_rust::types::{self, Level, Person, Server, Request, };
 
 /// The version of the rollbar-rs crate that is being used.
 pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
+/// The version of the payload schema emitted by this crate, which is included
+/// in every item sent to Rollbar. This is incremented whenever the shape of the
+/// payload changes in a way which tools processing it may need to be aware of.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[cfg(not(any(feature = "async", feature = "threaded")))]
+compile_error!("rollbar-rs requires either the `async` or `threaded` feature to be enabled so that it can send events to Rollbar.");
+
 lazy_static::lazy_static! {
     pub (in crate) static ref CONFIG: RwLock<Configuration> = RwLock::new(Configuration::default());
+    static ref DEFAULT_CLIENT: RwLock<Option<Arc<dyn Reporter>>> = RwLock::new(None);
+}
+
+/// Acquires a read lock on the global configuration, recovering it if another
+/// thread panicked while holding the lock.
+/// 
+/// An error reporting crate should never be the cause of cascading panics, and
+/// the configuration is always left in a consistent state, so it is safe to keep
+/// using it after the lock has been poisoned.
+fn read_config() -> RwLockReadGuard<'static, Configuration> {
+    CONFIG.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Acquires a write lock on the global configuration, recovering it if another
+/// thread panicked while holding the lock.
+fn write_config() -> RwLockWriteGuard<'static, Configuration> {
+    CONFIG.write().unwrap_or_else(|e| e.into_inner())
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Whether an `on_drop` hook is configured, mirrored from `CONFIG` so that events
+/// can be dropped without acquiring the configuration lock when there is none.
+static HAS_DROP_HOOK: AtomicBool = AtomicBool::new(false);
+
+/// Records that an event was dropped by the global client before its configuration
+/// was read, invoking the configured `on_drop` hook (if there is one).
+fn dropped(data: &types::Data, reason: DropReason) -> ReportOutcome {
+    if HAS_DROP_HOOK.load(Ordering::SeqCst) {
+        // The hook is called without holding the configuration lock, since it may
+        // report events or update the configuration itself.
+        let on_drop = read_config().on_drop.clone();
+        if let Some(on_drop) = on_drop {
+            on_drop(&models::without_extra_data(data), reason);
+        }
+    }
+
+    ReportOutcome::Dropped(reason)
+}
+
+/// The global log level, mirrored from `CONFIG` so that events can be filtered
+/// without acquiring the configuration lock.
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(1);
+
+fn level_to_u8(level: &types::Level) -> u8 {
+    match level {
+        types::Level::Debug => 0,
+        types::Level::Info => 1,
+        types::Level::Warning => 2,
+        types::Level::Error => 3,
+        types::Level::Critical => 4,
+    }
+}
+
+fn level_from_u8(level: u8) -> types::Level {
+    match level {
+        0 => types::Level::Debug,
+        1 => types::Level::Info,
+        2 => types::Level::Warning,
+        3 => types::Level::Error,
+        _ => types::Level::Critical,
+    }
+}
+
+#[cfg(test)]
+lazy_static::lazy_static! {
+    /// Serializes tests which modify global state (like the configuration or
+    /// the enabled flag) so that they do not interfere with one another.
+    pub (in crate) static ref TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
 }
 
 #[cfg(feature = "async")]
@@ -29,85 +144,366 @@ lazy_static::lazy_static! {
     pub (in crate) static ref TRANSPORT: TokioTransport = TokioTransport::new(&TransportConfig::default()).unwrap();
 }
 
+// When both transports are enabled, the async transport takes priority.
 #[cfg(feature = "threaded")]
 #[cfg(not(feature = "async"))]
 lazy_static::lazy_static! {
     pub (in crate) static ref TRANSPORT: ThreadedTransport = ThreadedTransport::new(&TransportConfig::default()).unwrap();
 }
 
+/// Waits for all of the events which are being sent by the global Rollbar client
+/// to complete, or for the timeout to elapse.
+/// 
+/// Events are sent in the background by the async transport, so you should call
+/// this before your application shuts down (or before a serverless invocation
+/// completes) to ensure that they are delivered.
+/// 
+/// # Example
+/// ```rust,no_run
+/// # async fn shutdown() {
+/// use std::time::Duration;
+/// 
+/// rollbar_rs::flush(Duration::from_secs(5)).await.ok();
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub async fn flush(timeout: std::time::Duration) -> Result<(), Error> {
+    TRANSPORT.flush(timeout).await
+}
+
+lazy_static::lazy_static! {
+    static ref RATE_LIMITER: rate_limiter::RateLimiter = rate_limiter::RateLimiter::default();
+}
+
+/// Gets a snapshot of the metrics gathered by the global Rollbar client's transport,
+/// including the number of events which were dropped because they exceeded the
+/// configured `max_events_per_window`.
+/// 
+/// Events reported through a client registered using [`set_default_client`] are
+/// tracked by that client's [`Client::metrics`] instead.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// println!("{} events were rate limited", metrics().rate_limited);
+/// ```
+pub fn metrics() -> TransportMetrics {
+    TransportMetrics {
+        rate_limited: RATE_LIMITER.dropped(),
+        ..TRANSPORT.metrics()
+    }
+}
+
+/// Enables or disables reporting through the global Rollbar client.
+/// 
+/// While disabled, any events reported using `rollbar!` or `report` are
+/// discarded immediately without being sent to the transport. This is useful
+/// when you wish to temporarily silence reporting, for example during a
+/// maintenance window or within tests.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Determines whether reporting through the global Rollbar client is enabled.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
 /// Removes any configured access token, disabling Rollbar.
 /// 
 /// This method can be used to disable Rollbar reporting at runtime
 /// without having to recompile your application.
 pub fn unset_token() {
-    CONFIG.write().map(|mut c| c.access_token = None).unwrap();
+    write_config().access_token = None;
 }
 
 pub fn set_token<S: Into<String>>(token: S) {
-    CONFIG.write().map(|mut c| c.access_token = Some(token.into())).unwrap();
+    write_config().access_token = Some(token.into());
 }
 
 pub fn set_environment<S: Into<String>>(environment: S) {
-    CONFIG.write().map(|mut c| c.environment = Some(environment.into())).unwrap();
+    write_config().environment = Some(environment.into());
 }
 
 pub fn set_host<S: Into<String>>(host: S) {
-    CONFIG.write().map(|mut c| c.host = Some(host.into())).unwrap();
+    write_config().host = Some(host.into());
 }
 
 pub fn set_code_version<S: Into<String>>(code_version: S) {
-    CONFIG.write().map(|mut c| c.code_version = Some(code_version.into())).unwrap();
+    write_config().code_version = Some(code_version.into());
 }
 
 pub fn set_log_level(level: types::Level) {
-    CONFIG.write().map(|mut c| c.log_level = level).unwrap();
+    let mut config = write_config();
+    config.log_level = level;
+    LOG_LEVEL.store(level_to_u8(&config.min_log_level()), Ordering::SeqCst);
+}
+
+/// Sets the minimum level at which events will be reported by the global client
+/// for a specific environment, overriding the global log level for it.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// set_log_level_for_environment("production", Level::Warning);
+/// set_log_level_for_environment("staging", Level::Debug);
+/// ```
+pub fn set_log_level_for_environment<S: Into<String>>(environment: S, level: types::Level) {
+    let mut config = write_config();
+    config.log_levels_by_environment.insert(environment.into(), level);
+    LOG_LEVEL.store(level_to_u8(&config.min_log_level()), Ordering::SeqCst);
+}
+
+/// Gets the minimum level at which events will be reported by the global client
+/// in any environment.
+/// 
+/// This is read without acquiring the configuration lock, making it cheap to
+/// call on hot paths.
+pub fn get_log_level() -> types::Level {
+    level_from_u8(LOG_LEVEL.load(Ordering::SeqCst))
 }
 
 pub fn set_platform<S: Into<String>>(platform: S) {
-    CONFIG.write().map(|mut c| c.platform = Some(platform.into())).unwrap();
+    write_config().platform = Some(platform.into());
 }
 
 pub fn set_framework<S: Into<String>>(framework: S) {
-    CONFIG.write().map(|mut c| c.framework = Some(framework.into())).unwrap();
+    write_config().framework = Some(framework.into());
 }
 
 pub fn set_context<S: Into<String>>(context: S) {
-    CONFIG.write().map(|mut c| c.context = Some(context.into())).unwrap();
+    write_config().context = Some(context.into());
 }
 
 pub fn set_custom<S: Into<String>>(key: S, value: serde_json::Value) {
-    CONFIG.write().map(|mut c| {
-        match c.custom {
-            Some(ref mut custom) => {
-                custom.insert(key.into(), value);
-            },
-            None => {
-                c.custom = Some(HashMap::new());
-                c.custom.as_mut().unwrap().insert(key.into(), value);
+    let mut config = write_config();
+    match config.custom {
+        Some(ref mut custom) => {
+            custom.insert(key.into(), value);
+        },
+        None => {
+            config.custom = Some(HashMap::new());
+            config.custom.as_mut().unwrap().insert(key.into(), value);
+        }
+    }
+}
+
+/// Sets the callback which is invoked whenever an event reported using the global
+/// client is dropped, along with the reason it was dropped.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// set_on_drop(|_data, reason| eprintln!("A Rollbar event was dropped: {:?}", reason));
+/// ```
+pub fn set_on_drop<F: Fn(&types::Data, DropReason) + Send + Sync + 'static>(on_drop: F) {
+    write_config().on_drop = Some(Arc::new(on_drop));
+    HAS_DROP_HOOK.store(true, Ordering::SeqCst);
+}
+
+/// Gets a copy of the effective global configuration, reflecting any changes made
+/// using the `set_*` functions.
+/// 
+/// This is useful for verifying the configuration that will be used to report
+/// events, for example by exposing it (using [`Configuration::to_redacted_json`])
+/// through a diagnostics endpoint.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// set_environment("production");
+/// assert_eq!(current_config().environment, Some("production".to_string()));
+/// ```
+pub fn current_config() -> Configuration {
+    read_config().clone()
+}
+
+/// Runs the provided closure with the global configuration replaced by the provided
+/// one, restoring the previous configuration once it completes (even if it panics).
+/// 
+/// This affects the global configuration, so events reported by other threads
+/// while the closure is running will also use the provided configuration. It is
+/// primarily intended for use in tests and short, specific code paths.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// with_config(Configuration {
+///     environment: Some("staging".to_string()),
+///     ..Default::default()
+/// }, || {
+///     rollbar!(message = "This is reported in the staging environment");
+/// });
+/// ```
+pub fn with_config<R, F: FnOnce() -> R>(config: Configuration, f: F) -> R {
+    struct Restore(Option<Configuration>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(config) = self.0.take() {
+                replace_config(config);
             }
         }
-    }).unwrap();
+    }
+
+    let _restore = Restore(Some(replace_config(config)));
+    f()
+}
+
+fn replace_config(config: Configuration) -> Configuration {
+    let mut current = write_config();
+    LOG_LEVEL.store(level_to_u8(&config.min_log_level()), Ordering::SeqCst);
+    HAS_DROP_HOOK.store(config.on_drop.is_some(), Ordering::SeqCst);
+    std::mem::replace(&mut *current, config)
+}
+
+/// Registers a client which will be used to report events sent through the global
+/// [`report`] function and the [`rollbar!`] macro, in place of the global
+/// configuration and transport.
+/// 
+/// This allows you to use a custom transport (or configuration) while continuing
+/// to use the convenient global reporting macros. Reporting may still be disabled
+/// globally using [`set_enabled`].
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// let client = Client::with_default_transport(Configuration::default()).unwrap();
+/// 
+/// set_default_client(client);
+/// rollbar!(message = "This is reported using the default client");
+/// clear_default_client();
+/// ```
+pub fn set_default_client<R: Reporter + 'static>(client: R) {
+    *DEFAULT_CLIENT.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(client));
+}
+
+/// Removes the client registered using [`set_default_client`], causing events to
+/// be reported using the global configuration and transport once more.
+pub fn clear_default_client() {
+    *DEFAULT_CLIENT.write().unwrap_or_else(|e| e.into_inner()) = None;
 }
 
 pub fn report(data: types::Data) {
+    try_report(data);
+}
+
+/// Reports an event to Rollbar using the global client, returning whether it
+/// was queued for sending or the reason it was dropped.
+/// 
+/// This is useful when debugging why an event did not appear in Rollbar.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// match try_report(rollbar_format!(Debug message = "Hello, world!")) {
+///     ReportOutcome::Queued { uuid } => println!("Queued event {}", uuid),
+///     ReportOutcome::Dropped(reason) => println!("Dropped event: {:?}", reason),
+/// }
+/// ```
+pub fn try_report(data: types::Data) -> ReportOutcome {
+    if !is_enabled() {
+        return dropped(&data, DropReason::Disabled);
+    }
+
+    if let Some(client) = DEFAULT_CLIENT.read().unwrap_or_else(|e| e.into_inner()).clone() {
+        return client.try_report(data);
+    }
+
+    // Events without a level are reported at the `Info` level, so we can filter
+    // them here without needing to acquire the configuration lock. The global log
+    // level is the lowest level for any environment, so environment specific levels
+    // are checked once the configuration has been read.
+    if level_to_u8(data.level.as_ref().unwrap_or(&types::Level::Info)) < LOG_LEVEL.load(Ordering::SeqCst) {
+        return dropped(&data, DropReason::BelowLogLevel);
+    }
+
     lazy_static::initialize(&TRANSPORT);
 
-    let config = CONFIG.read().unwrap();
+    // The configuration is copied so that the lock is not held while the event is
+    // prepared (which may involve symbolicating its backtrace) or passed to hooks.
+    let config = read_config().clone();
 
-    let cfg: &Configuration = &config;
+    let mut data = data;
+    helpers::map_level(&mut data, &config);
 
-    let payload: models::Item = (data, cfg).into();
+    let environment = data.environment.as_deref().or(config.environment.as_deref());
+    if data.level.as_ref().unwrap_or(&types::Level::Info) < &config.log_level_for(environment) {
+        return config.dropped(&data, DropReason::BelowLogLevel);
+    }
 
-    if let Some(level) = payload.data.level.clone() {
-        if level < config.log_level {
-            return;
+    if config.access_token.is_none() {
+        transport::warn_no_access_token();
+        return config.dropped(&data, DropReason::NoToken);
+    }
+
+    if let Some((max_events, window)) = config.max_events_per_window {
+        match RATE_LIMITER.admit(max_events, window) {
+            rate_limiter::RateLimit::Exceeded => return config.dropped(&data, DropReason::RateLimited),
+            rate_limiter::RateLimit::Allowed { suppressed: Some(suppressed) } => TRANSPORT.send(TransportEvent {
+                config: &config,
+                payload: (rate_limiter::summary(suppressed, max_events, window), &config).into(),
+            }),
+            rate_limiter::RateLimit::Allowed { suppressed: None } => {},
         }
     }
 
+    let payload: models::Item = (data, &config).into();
+    let uuid = payload.data.uuid.clone().unwrap_or_default();
+
     TRANSPORT.send(TransportEvent {
         config: &config,
         payload,
-    })
+    });
+
+    ReportOutcome::Queued { uuid }
+}
+
+/// Reports a panic which was caught using `std::panic::catch_unwind`.
+/// 
+/// This allows libraries which catch panics at plugin or FFI boundaries to report
+/// them to Rollbar without installing a global panic hook. The panic's message is
+/// extracted from its payload in the same way as `handle_panics!`, and the event
+/// is reported at the `Critical` level.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// if let Err(payload) = std::panic::catch_unwind(|| panic!("Plugin crashed")) {
+///     report_caught_panic(payload.as_ref(), None);
+/// }
+/// ```
+pub fn report_caught_panic(payload: &(dyn std::any::Any + Send), location: Option<&std::panic::Location>) {
+    report(helpers::get_panic_data(payload, location));
+}
+
+/// Reports a dynamically typed error, such as a `Box<dyn Error>`, to Rollbar at the
+/// provided level.
+/// 
+/// The `error = ...` form of the [`rollbar!`] macro determines the error's class from
+/// its static type, which is unhelpful for boxed errors. This instead attempts to
+/// determine the class of the underlying error at runtime.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// let err: Box<dyn std::error::Error + Send + Sync> = "Something went wrong".into();
+/// report_boxed_error(err.as_ref(), Level::Error);
+/// ```
+#[track_caller]
+pub fn report_boxed_error(err: &(dyn std::error::Error + 'static), level: types::Level) {
+    let mut data = helpers::get_dyn_error_data(err);
+    data.level = Some(level);
+    report(data);
 }
 
 #[cfg(test)]
@@ -116,7 +512,195 @@ mod tests {
 
     #[test]
     fn test_global_config() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
         set_token("test_token");
         assert_eq!(CONFIG.read().unwrap().access_token, Some("test_token".to_string()));
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[cfg(all(feature = "async", feature = "threaded"))]
+    fn test_async_transport_priority() {
+        fn is_tokio_transport(_: &TokioTransport) -> bool { true }
+
+        assert!(is_tokio_transport(&TRANSPORT), "the async transport should take priority when both are enabled");
+        assert!(ThreadedTransport::new(&TransportConfig::default()).is_ok(), "the threaded transport should remain available");
+    }
+
+    #[test]
+    fn test_log_level() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        assert_eq!(get_log_level(), Level::Info);
+
+        for level in [Level::Debug, Level::Warning, Level::Error, Level::Critical, Level::Info] {
+            set_log_level(level.clone());
+            assert_eq!(get_log_level(), level);
+            assert_eq!(CONFIG.read().unwrap().log_level, level);
+        }
+
+        set_log_level(Level::Warning);
+        assert_eq!(try_report(rollbar_format!(Info message = "Below the global log level")), ReportOutcome::Dropped(DropReason::BelowLogLevel));
+        set_log_level(Level::Info);
+    }
+
+    #[test]
+    fn test_try_report() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        assert_eq!(try_report(rollbar_format!(Debug message = "Below the log level")), ReportOutcome::Dropped(DropReason::BelowLogLevel));
+
+        unset_token();
+        assert_eq!(try_report(rollbar_format!(Error message = "Without a token")), ReportOutcome::Dropped(DropReason::NoToken));
+    }
+
+    #[test]
+    fn test_enabled() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        assert!(is_enabled(), "reporting should be enabled by default");
+
+        set_enabled(false);
+        assert!(!is_enabled());
+        assert_eq!(try_report(rollbar_format!(Critical message = "This message should be suppressed")), ReportOutcome::Dropped(DropReason::Disabled));
+
+        set_enabled(true);
+        assert!(is_enabled());
+    }
+
+    #[test]
+    fn test_on_drop_updates_config() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let config = Configuration {
+            on_drop: Some(Arc::new(|_data: &types::Data, _reason| set_context("dropped"))),
+            ..current_config()
+        };
+
+        with_config(config, || {
+            set_enabled(false);
+            let outcome = try_report(rollbar_format!(Critical message = "This message should be suppressed"));
+            set_enabled(true);
+
+            assert_eq!(outcome, ReportOutcome::Dropped(DropReason::Disabled));
+            assert_eq!(current_config().context, Some("dropped".to_string()), "the hook should be able to update the configuration");
+        });
+    }
+
+    #[test]
+    fn test_report_boxed_error() {
+        let err: Box<dyn std::error::Error + Send + Sync> = Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Something went wrong"));
+        let data = helpers::get_dyn_error_data(err.as_ref());
+
+        match data.body {
+            types::Body::TraceBody { trace, .. } => {
+                assert!(!trace.exception.class.is_empty());
+                assert!(!trace.exception.class.contains("Box"), "the class should describe the boxed error, not the box");
+                assert_eq!(trace.exception.message, Some("Something went wrong".to_string()));
+            },
+            _ => panic!("Expected trace body")
+        }
+
+        report_boxed_error(err.as_ref(), types::Level::Error);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let before = metrics().rate_limited;
+        let config = Configuration {
+            access_token: Some("test_token".to_string()),
+            max_events_per_window: Some((0, std::time::Duration::from_secs(60))),
+            ..current_config()
+        };
+
+        with_config(config, || {
+            assert_eq!(try_report(rollbar_format!(Error message = "Over the limit")), ReportOutcome::Dropped(DropReason::RateLimited));
+            assert_eq!(try_report(rollbar_format!(Error message = "Over the limit")), ReportOutcome::Dropped(DropReason::RateLimited));
+        });
+
+        assert_eq!(metrics().rate_limited - before, 2, "events dropped by the global rate limiter should be counted");
+    }
+
+    #[test]
+    fn test_default_client() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let transport = transport::MockTransport::default();
+        set_default_client(Client::new(transport.clone(), Configuration {
+            access_token: Some("test_token".to_string()),
+            ..Default::default()
+        }));
+
+        let id = helpers::new_uuid();
+        rollbar!(message = format!("This is reported using the default client ({})", id));
+        clear_default_client();
+        rollbar!(message = format!("This is reported using the global transport ({})", id));
+
+        // Other tests may report events through the global client while the default client is
+        // registered, so only the events reported by this test are considered.
+        let messages: Vec<String> = transport.items().iter()
+            .filter_map(|item| item.data.message_text().map(|m| m.to_string()))
+            .filter(|message| message.contains(&id))
+            .collect();
+        assert_eq!(messages, vec![format!("This is reported using the default client ({})", id)]);
+    }
+
+    #[test]
+    fn test_with_config() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        set_environment("development");
+
+        let environment = with_config(Configuration {
+            environment: Some("staging".to_string()),
+            log_level: Level::Warning,
+            ..Default::default()
+        }, || {
+            assert_eq!(get_log_level(), Level::Warning);
+            CONFIG.read().unwrap().environment.clone()
+        });
+
+        assert_eq!(environment, Some("staging".to_string()));
+        assert_eq!(CONFIG.read().unwrap().environment, Some("development".to_string()));
+        assert_eq!(get_log_level(), Level::Info);
+
+        let result = std::panic::catch_unwind(|| with_config(Configuration::default(), || panic!("The closure panicked")));
+        assert!(result.is_err());
+        assert_eq!(CONFIG.read().unwrap().environment, Some("development".to_string()), "the configuration should be restored after a panic");
+    }
+
+    #[test]
+    fn test_poisoned_config() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let _ = std::thread::spawn(|| {
+            let _config = CONFIG.write().unwrap();
+            panic!("Poisoning the configuration lock");
+        }).join();
+
+        assert!(CONFIG.is_poisoned());
+
+        set_environment("testing");
+        assert_eq!(read_config().environment, Some("testing".to_string()));
+        assert_eq!(try_report(rollbar_format!(Debug message = "Below the log level")), ReportOutcome::Dropped(DropReason::BelowLogLevel));
+        report(rollbar_format!(message = "Reported after the lock was poisoned"));
+
+        CONFIG.clear_poison();
+    }
+
+    #[test]
+    fn test_current_config() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        set_environment("production");
+        set_code_version("1.2.3");
+        set_custom("service", serde_json::json!("api"));
+
+        let config = current_config();
+        assert_eq!(config.environment, Some("production".to_string()));
+        assert_eq!(config.code_version, Some("1.2.3".to_string()));
+        assert_eq!(config.custom.unwrap()["service"], "api");
+    }
+}
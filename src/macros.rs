@@ -23,7 +23,9 @@
 /// ## Custom Fields
 /// You can also specify custom fields which are included in your event by setting
 /// the `custom` field. A `map!` macro is provided to simplify the generation of 
-/// the appropriate data structure.
+/// the appropriate data structure. These are merged with any custom fields added
+/// by other options (like `grouping_key`), with the values you provide taking
+/// precedence.
 /// ```rust
 /// use rollbar_rs::*;
 /// 
@@ -38,6 +40,10 @@ macro_rules! rollbar {
     (error = $err:expr $(,$key:ident = $val:expr)*) => {
         $crate::report($crate::rollbar_format!(error = $err $(, $key = $val)*));
     };
+
+    (error_chain = $err:expr $(,$key:ident = $val:expr)*) => {
+        $crate::report($crate::rollbar_format!(error_chain = $err $(, $key = $val)*));
+    };
     
     ($level:ident message = $msg:expr $(, { $($extra_key:ident: $extra_val:expr),+ })? $(,$key:ident = $val:expr)*) => {
         $crate::report($crate::rollbar_format!($level message = $msg $(, { $($extra_key: $extra_val),+ })? $(, $key = $val)*));
@@ -46,6 +52,63 @@ macro_rules! rollbar {
     ($level:ident error = $err:expr $(,$key:ident = $val:expr)*) => {
         $crate::report($crate::rollbar_format!($level error = $err $(, $key = $val)*));
     };
+
+    ($level:ident error_chain = $err:expr $(,$key:ident = $val:expr)*) => {
+        $crate::report($crate::rollbar_format!($level error_chain = $err $(, $key = $val)*));
+    };
+}
+
+/// Reports an event to Rollbar using the default client, in the same way as
+/// [`rollbar!`], and then returns the value provided as its final argument from
+/// the enclosing function.
+/// 
+/// This reduces the boilerplate in handlers which report an error and fall back to
+/// a default value. Since it expands to a `return` statement, it may only be used
+/// within a function (or closure), and it returns from the innermost one. Any code
+/// following it in the same block is unreachable, and the event is always reported
+/// (or dropped, for example if it is below the log level) before returning.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// fn parse_port(value: &str) -> u16 {
+///     match value.parse() {
+///         Ok(port) => port,
+///         Err(e) => report_and_return!(Warning error = e, context = "config#port", 8080),
+///     }
+/// }
+/// 
+/// assert_eq!(parse_port("443"), 443);
+/// assert_eq!(parse_port("not a port"), 8080);
+/// ```
+#[macro_export]
+macro_rules! report_and_return {
+    ($($tokens:tt)+) => {
+        $crate::__rollbar_report_and_return!([] [] $($tokens)+)
+    };
+}
+
+/// Splits the arguments of [`report_and_return!`] on their top-level commas, so
+/// that the final argument can be used as the return value while the others are
+/// passed to [`rollbar!`].
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rollbar_report_and_return {
+    ([$($args:tt)*] [$($current:tt)*] , $($rest:tt)+) => {
+        $crate::__rollbar_report_and_return!([$($args)* ($($current)*)] [] $($rest)+)
+    };
+
+    ([$($args:tt)*] [$($current:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__rollbar_report_and_return!([$($args)*] [$($current)* $next] $($rest)*)
+    };
+
+    ([$(($($arg:tt)*))+] [$($value:tt)+]) => {
+        {
+            $crate::rollbar!($($($arg)*),+);
+            return $($value)+;
+        }
+    };
 }
 
 /// Generates a Rollbar data payload which can be submitted to the Rollbar API.
@@ -83,29 +146,116 @@ macro_rules! rollbar {
 /// let client = rollbar_rs::Client::with_default_transport(rollbar_rs::Configuration::default()).unwrap();
 /// client.report(rollbar_format!(Info message = "This is an example with custom fields.", { foo: "bar" }, custom = map!{ owner: "Bob" }));
 /// ```
+///
+/// ## Event Identifiers
+/// A unique `uuid` is generated for each event when it is reported, however you
+/// may provide your own (for example, to correlate the event with your logs) by
+/// setting the `uuid` field explicitly.
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let data = rollbar_format!(message = "This event has a known ID.", uuid = "3a7e1a6b-1c8f-4a53-9c1e-7e3c5d1f0b2a");
+/// assert_eq!(data.uuid, Some("3a7e1a6b-1c8f-4a53-9c1e-7e3c5d1f0b2a".to_string()));
+/// ```
+///
+/// ## Timestamps
+/// Events are timestamped with the time at which they are reported, however you
+/// may provide the Unix timestamp (in seconds) at which an event occurred if it
+/// is being reported after the fact (for example, when replaying logs).
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let data = rollbar_format!(message = "This happened a while ago.", timestamp = 1_600_000_000u64);
+/// assert_eq!(data.timestamp, Some(1_600_000_000));
+/// ```
+/// 
+/// ## Error Chains
+/// Errors which wrap other errors may be reported using `error_chain = ...`, which
+/// includes a trace for each error in its `source()` chain, allowing Rollbar to
+/// display the full causal chain.
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let err = std::io::Error::new(std::io::ErrorKind::Other, "Some error");
+/// let data = rollbar_format!(Error error_chain = err);
+/// ```
+/// 
+/// ## Extra Data
+/// Top-level fields of Rollbar's item schema which are not modelled by this crate
+/// may be provided using `extra_data = ...`. These are merged into the event when
+/// it is serialized, however any field which is also set on the event itself takes
+/// precedence over the value provided here.
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let data = rollbar_format!(message = "Using a newer schema field", extra_data = map!{ attributes: ["beta"] });
+/// ```
+/// 
+/// ## Occurrences
+/// Applications which aggregate identical errors themselves may report the number
+/// of times an error occurred using `occurrences = ...`, which is stored in the
+/// event's `custom.occurrences` field, rather than sending an event for each one.
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let data = rollbar_format!(message = "Cache miss storm", occurrences = 42);
+/// ```
+/// 
+/// ## Grouping Keys
+/// A coarse grouping key (like the service and category of an error) may be provided
+/// using `grouping_key = ...`, which is stored in the event's `custom.grouping_key`
+/// field so that dashboards can group events whose fingerprints differ. When no
+/// `fingerprint = ...` is provided, the grouping key is also used as the event's
+/// fingerprint; an explicit fingerprint always takes precedence, regardless of the
+/// order in which they are provided.
+/// ```rust
+/// use rollbar_rs::*;
+///
+/// let data = rollbar_format!(message = "Payment declined", grouping_key = "billing/payments");
+/// assert_eq!(data.fingerprint, Some("billing/payments".to_string()));
+///
+/// let data = rollbar_format!(message = "Payment declined", grouping_key = "billing/payments", fingerprint = "card-expired");
+/// assert_eq!(data.fingerprint, Some("card-expired".to_string()));
+/// ```
+/// 
+/// ## Levels
+/// The level of an event must be one of `Critical`, `Error`, `Warning`, `Info`
+/// or `Debug`. Any other level results in a compile error listing the valid levels.
+/// ```rust,compile_fail
+/// use rollbar_rs::*;
+///
+/// let data = rollbar_format!(Warn message = "This level doesn't exist");
+/// ```
 #[macro_export]
 macro_rules! rollbar_format {
     (message = $msg:expr $(, { $($extra_key:ident: $extra_val:expr),+ })? $(,$key:ident = $val:expr)*) => {
-        $crate::types::Data {
-            body: $crate::types::Body::MessageBody {
-                telemetry: None,
-                message: $crate::types::Message {
-                    body: $msg.into(),
-                    extra: $crate::map!{$($($extra_key : $extra_val),+)?},
-                }
-            },
-            notifier: Some($crate::types::Notifier {
-                name: Some("SierraSoftworks/rollbar-rs".into()),
-                version: Some($crate::VERSION.into()),
-            }),
-            $($key: Some($val.into()),)*
-            ..Default::default()
+        {
+            #[allow(unused_mut)]
+            let mut data = $crate::types::Data {
+                body: $crate::types::Body::MessageBody {
+                    telemetry: None,
+                    message: $crate::types::Message {
+                        body: $msg.into(),
+                        extra: $crate::map!{$($($extra_key : $extra_val),+)?},
+                    }
+                },
+                notifier: Some($crate::types::Notifier {
+                    name: Some("SierraSoftworks/rollbar-rs".into()),
+                    version: Some($crate::VERSION.into()),
+                }),
+                ..Default::default()
+            };
+
+            $($crate::__rollbar_field!(data, $key, $val);)*
+            $crate::helpers::attach_message_source(&mut data, file!(), line!(), module_path!());
+            data
         }
     };
 
     (error = $err:expr $(,$key:ident = $val:expr)*) => {
         {
-            let mut frames = $crate::helpers::get_backtrace_frames();
+            let err = &$err;
+            let mut frames = vec![];
             let line = line!() - 3;
 
             frames.push($crate::types::Frame {
@@ -114,11 +264,11 @@ macro_rules! rollbar_format {
                 ..Default::default()
             });
 
-            $crate::types::Data {
+            let mut data = $crate::types::Data {
                 body: $crate::types::Body::TraceBody {
                     telemetry: None,
                     trace: $crate::types::Trace {
-                        exception: $crate::helpers::get_exception(&$err),
+                        exception: $crate::helpers::get_exception(err),
                         frames: frames,
                     }
                 },
@@ -126,16 +276,29 @@ macro_rules! rollbar_format {
                     name: Some("SierraSoftworks/rollbar-rs".into()),
                     version: Some($crate::VERSION.into()),
                 }),
-                $($key: Some($val.into()),)*
                 ..Default::default()
-            }
+            };
+
+            $($crate::__rollbar_field!(data, $key, $val);)*
+            $crate::helpers::attach_error_debug(&mut data, err);
+            $crate::helpers::attach_unresolved_backtrace(&mut data);
+            data
+        }
+    };
+
+    (error_chain = $err:expr $(,$key:ident = $val:expr)*) => {
+        {
+            #[allow(unused_mut)]
+            let mut data = $crate::helpers::get_error_chain_data(&$err);
+            $($crate::__rollbar_field!(data, $key, $val);)*
+            data
         }
     };
 
     ($level:ident message = $msg:expr $(, { $($extra_key:ident: $extra_val:expr),+ })? $(,$key:ident = $val:expr)*) => {
         {
             let mut data = $crate::rollbar_format!(message = $msg $(, { $($extra_key: $extra_val),+ })? $(,$key = $val)*);
-            data.level = Some($crate::Level::$level);
+            data.level = Some($crate::__rollbar_level!($level));
             data
         }
     };
@@ -143,7 +306,143 @@ macro_rules! rollbar_format {
     ($level:ident error = $err:expr $(,$key:ident = $val:expr)*) => {
         {
             let mut data = $crate::rollbar_format!(error = $err $(,$key = $val)*);
-            data.level = Some($crate::Level::$level);
+            data.level = Some($crate::__rollbar_level!($level));
+            data
+        }
+    };
+
+    ($level:ident error_chain = $err:expr $(,$key:ident = $val:expr)*) => {
+        {
+            let mut data = $crate::rollbar_format!(error_chain = $err $(,$key = $val)*);
+            data.level = Some($crate::__rollbar_level!($level));
+            data
+        }
+    };
+}
+
+/// Sets a field on an event generated by the reporting macros, handling fields
+/// (like `extra_data`) which do not map directly onto a field of `Data`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rollbar_field {
+    ($data:ident, extra_data, $val:expr) => {
+        $crate::helpers::attach_extra_data(&mut $data, $val)
+    };
+
+    ($data:ident, occurrences, $val:expr) => {
+        $crate::helpers::attach_occurrences(&mut $data, $val)
+    };
+
+    ($data:ident, grouping_key, $val:expr) => {
+        $crate::helpers::attach_grouping_key(&mut $data, $val)
+    };
+
+    ($data:ident, custom, $val:expr) => {
+        $crate::helpers::merge_custom(&mut $data, $val)
+    };
+
+    ($data:ident, $key:ident, $val:expr) => {
+        $data.$key = Some($val.into())
+    };
+}
+
+/// Resolves the level identifier used by the reporting macros, producing a
+/// helpful compile error if it is not one of the known levels.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rollbar_level {
+    (Critical) => { $crate::Level::Critical };
+    (Error) => { $crate::Level::Error };
+    (Warning) => { $crate::Level::Warning };
+    (Info) => { $crate::Level::Info };
+    (Debug) => { $crate::Level::Debug };
+    ($other:ident) => {
+        compile_error!(concat!("unknown Rollbar level `", stringify!($other), "`, expected one of: Critical, Error, Warning, Info, Debug"))
+    };
+}
+
+/// Reports a structured log message to Rollbar using the default client.
+/// 
+/// The message is provided using `message = ...` (optionally preceded by its level),
+/// in the same way as [`rollbar!`], so it may be any expression, including a call to
+/// a function or to `format!`. Each `key = value` pair following the message is stored in the message's
+/// `extra` data, making it searchable in Rollbar. Reserved fields of the event
+/// (like `environment` or `context`) may be set after a `;`, which ensures that
+/// there is never any ambiguity between the two: a pair before the `;` is always
+/// stored as extra data, even if it shares its name with a reserved field.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// rollbar_kv!(Info message = "User upgraded their plan", user_id = 42, plan = "pro");
+/// rollbar_kv!(Warning message = format!("Payment of ${} declined", 42), user_id = 42, environment = "eu-west"; environment = "production");
+/// ```
+#[macro_export]
+macro_rules! rollbar_kv {
+    (message = $msg:expr $(, $key:ident = $val:expr)* $(; $($field:ident = $fval:expr),+)?) => {
+        $crate::rollbar_kv!(Info message = $msg $(, $key = $val)* $(; $($field = $fval),+)?);
+    };
+
+    ($level:ident message = $msg:expr $(, $key:ident = $val:expr)* $(; $($field:ident = $fval:expr),+)?) => {
+        $crate::report($crate::__rollbar_kv_format!($level message = $msg $(, $key = $val)* $(; $($field = $fval),+)?));
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rollbar_kv_format {
+    ($level:ident message = $msg:expr $(, $key:ident = $val:expr)* $(; $($field:ident = $fval:expr),+)?) => {
+        {
+            let mut data = $crate::rollbar_format!($level message = $msg $($(, $field = $fval)+)?);
+            if let $crate::types::Body::MessageBody { message, .. } = &mut data.body {
+                message.extra.extend($crate::map!{$($key: $val),*});
+            }
+
+            data
+        }
+    };
+}
+
+/// Reports an event to Rollbar using the default client, capturing the values of
+/// the named local variables in its `custom` data.
+/// 
+/// Each variable is recorded under its own name using its `Debug` representation,
+/// saving you from needing to build a [`map!`] of the values which are relevant
+/// at the point an error occurred. Any other fields supported by [`rollbar!`] may
+/// be provided after the list of captured variables.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// let user_id = 42;
+/// let order_id = "ord_123";
+/// let err = std::io::Error::new(std::io::ErrorKind::Other, "Payment provider unavailable");
+/// rollbar_ctx!(Error error = err, capture: [user_id, order_id], context = "orders#create");
+/// ```
+#[macro_export]
+macro_rules! rollbar_ctx {
+    ($kind:ident = $val:expr, capture: [$($var:ident),+ $(,)?] $(, $key:ident = $kval:expr)*) => {
+        $crate::report($crate::__rollbar_ctx_format!($kind = $val, capture: [$($var),+] $(, $key = $kval)*));
+    };
+
+    ($level:ident $kind:ident = $val:expr, capture: [$($var:ident),+ $(,)?] $(, $key:ident = $kval:expr)*) => {
+        $crate::report($crate::__rollbar_ctx_format!($level $kind = $val, capture: [$($var),+] $(, $key = $kval)*));
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rollbar_ctx_format {
+    ($($level:ident)? $kind:ident = $val:expr, capture: [$($var:ident),+] $(, $key:ident = $kval:expr)*) => {
+        {
+            let mut data = $crate::rollbar_format!($($level)? $kind = $val $(, $key = $kval)*);
+            let custom = data.custom.get_or_insert_with(Default::default);
+            $(
+                custom.insert(stringify!($var).to_string(), format!("{:?}", $var).into());
+            )+
+
             data
         }
     };
@@ -178,6 +477,13 @@ macro_rules! map {
 
 /// Configures Rollbar to handle any panics which occur within your
 /// application, reporting them as exceptions at the specified level.
+/// 
+/// Panics which occur while a panic is already being reported on the same
+/// thread are not reported, preventing the hook from recursing into itself.
+/// When [`Configuration::panic_coalesce_window`] is set, only the first of the
+/// identical panics which occur on any thread within the window is reported.
+/// 
+/// [`Configuration::panic_coalesce_window`]: crate::Configuration::panic_coalesce_window
 #[macro_export]
 macro_rules! handle_panics {
     ($($key:ident = $val:expr),*) => {
@@ -186,53 +492,46 @@ macro_rules! handle_panics {
 
     ($level:ident $(,$key:ident = $val:expr)*) => {
         ::std::panic::set_hook(::std::boxed::Box::new(move |panic_info| {
-            let payload = panic_info.payload();
-            let message = match payload.downcast_ref::<&str>() {
-                Some(s) => s,
-                None => match payload.downcast_ref::<String>() {
-                    Some(s) => s,
-                    None => "Panic",
-                }
-            };
-
-            let frames = if let Some(location) = panic_info.location() {
-                vec![
-                    $crate::types::Frame {
-                        filename: location.file().into(),
-                        lineno: Some(location.line()).map(|l| l as i32),
-                        colno: Some(location.column()).map(|c| c as i32),
-                        ..Default::default()
-                    },
-                ]
-            } else {
-                vec![]
-            };
+            $crate::helpers::guard_panic_hook(|| {
+                let mut data = $crate::helpers::get_panic_data(panic_info.payload(), panic_info.location());
+                data.level = Some($crate::__rollbar_level!($level));
+                $($crate::__rollbar_field!(data, $key, $val);)*
 
-            $crate::report($crate::types::Data {
-                body: $crate::types::Body::TraceBody {
-                    telemetry: None,
-                    trace: $crate::types::Trace {
-                        exception: $crate::types::Exception {
-                            class: "<panic>".into(),
-                            message: Some(message.into()),
-                            description: Some(message.into()),
-                            ..Default::default()
-                        },
-                        frames: frames,
-                    }
-                },
-                level: Some($crate::Level::$level),
-                notifier: Some($crate::types::Notifier {
-                    name: Some("SierraSoftworks/rollbar-rs".into()),
-                    version: Some($crate::VERSION.into()),
-                }),
-                $($key: Some($val.into()),)*
-                ..Default::default()
+                if $crate::helpers::coalesce_panic(&mut data, panic_info.payload(), panic_info.location()) {
+                    $crate::report(data);
+                }
             });
         }));
     };
 }
 
+/// Configures Rollbar to handle panics in the same way as [`handle_panics!`],
+/// unless your application is running under a test harness.
+/// 
+/// Panics are how failing tests are reported, so reporting them to Rollbar
+/// produces noise and interferes with the test harness' own output. This does
+/// nothing when compiled with `cfg(test)`, or when [`helpers::is_test_harness`]
+/// detects that `cargo nextest` (or `cargo test` with `RUST_TEST_THREADS` set) is
+/// running, leaving the default panic hook in place. Tests which are not compiled
+/// with `cfg(test)` (such as doc tests) are not detected under plain `cargo test`.
+/// 
+/// [`helpers::is_test_harness`]: crate::helpers::is_test_harness
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// handle_panics_unless_testing!(Critical);
+/// ```
+#[macro_export]
+macro_rules! handle_panics_unless_testing {
+    ($($args:tt)*) => {
+        if !cfg!(test) && !$crate::helpers::is_test_harness() {
+            $crate::handle_panics!($($args)*);
+        }
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
@@ -243,6 +542,215 @@ mod tests {
         rollbar!(Debug message= "Hello, world!", environment = "production", context = "test", custom = map!{foo: "bar"});
     }
 
+    #[test]
+    fn test_report_and_return() {
+        let _lock = crate::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        fn parse_port(value: &str) -> u16 {
+            match value.parse() {
+                Ok(port) => port,
+                Err(e) => report_and_return!(Warning error = e, context = "config#port", 8080),
+            }
+        }
+
+        let reported = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let port = crate::with_config(crate::Configuration {
+            on_drop: Some({
+                let reported = reported.clone();
+                std::sync::Arc::new(move |data: &crate::types::Data, _reason| reported.lock().unwrap().push(data.context.clone()))
+            }),
+            ..Default::default()
+        }, || parse_port("not a port"));
+
+        assert_eq!(port, 8080);
+        assert_eq!(*reported.lock().unwrap(), vec![Some("config#port".to_string())], "the error should be reported before returning");
+    }
+
+    #[test]
+    fn generate_report_with_occurrences() {
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+        let data = rollbar_format!(error = err, occurrences = 42);
+        assert_eq!(data.custom.as_ref().map(|c| c["occurrences"].clone()), Some(serde_json::json!(42)));
+
+        let data = rollbar_format!(message = "Cache miss storm", occurrences = 42, custom = map!{ cache: "sessions" });
+        let custom = data.custom.expect("the custom field should be populated");
+        assert_eq!(custom["occurrences"], 42, "custom data should not replace the occurrences");
+        assert_eq!(custom["cache"], "sessions");
+
+        let data = rollbar_format!(message = "Cache miss storm", custom = map!{ cache: "sessions" }, occurrences = 42);
+        let custom = data.custom.expect("the custom field should be populated");
+        assert_eq!(custom["occurrences"], 42);
+        assert_eq!(custom["cache"], "sessions");
+    }
+
+    #[test]
+    fn generate_report_with_grouping_key() {
+        let data = rollbar_format!(message = "Payment declined", grouping_key = "billing/payments");
+        assert_eq!(data.custom.as_ref().map(|c| c["grouping_key"].clone()), Some(serde_json::json!("billing/payments")));
+        assert_eq!(data.fingerprint, Some("billing/payments".to_string()), "the grouping key should be used as the fingerprint");
+
+        let data = rollbar_format!(message = "Payment declined", fingerprint = "card-expired", grouping_key = "billing/payments");
+        assert_eq!(data.custom.as_ref().map(|c| c["grouping_key"].clone()), Some(serde_json::json!("billing/payments")));
+        assert_eq!(data.fingerprint, Some("card-expired".to_string()), "an explicit fingerprint should take precedence");
+    }
+
+    #[test]
+    fn generate_report_with_grouping_key_and_custom() {
+        let data = rollbar_format!(message = "Payment declined", grouping_key = "billing/payments", custom = map!{ owner: "Bob" });
+        let custom = data.custom.expect("the custom field should be populated");
+        assert_eq!(custom["grouping_key"], "billing/payments", "custom data should not replace the grouping key");
+        assert_eq!(custom["owner"], "Bob");
+
+        let data = rollbar_format!(message = "Payment declined", custom = map!{ owner: "Bob" }, grouping_key = "billing/payments");
+        let custom = data.custom.expect("the custom field should be populated");
+        assert_eq!(custom["grouping_key"], "billing/payments");
+        assert_eq!(custom["owner"], "Bob");
+    }
+
+    #[test]
+    fn generate_error_report_without_backtrace() {
+        let config = crate::Configuration {
+            capture_backtrace: false,
+            ..Default::default()
+        };
+
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+        let item: crate::models::Item = (rollbar_format!(error = err), &config).into();
+
+        match item.data.body {
+            crate::types::Body::TraceBody { trace, .. } => {
+                assert_eq!(trace.frames.len(), 1, "only the call-site frame should be included");
+                assert_eq!(trace.frames[0].filename, file!().to_string());
+                assert!(trace.frames[0].method.is_none(), "no symbolicated frames should be produced");
+            },
+            _ => panic!("Unexpected trace type")
+        }
+    }
+
+    #[test]
+    fn generate_error_chain_report() {
+        #[derive(Debug)]
+        struct OuterError(std::io::Error);
+
+        impl std::fmt::Display for OuterError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "We could not load the configuration.")
+            }
+        }
+
+        impl std::error::Error for OuterError {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let err = OuterError(std::io::Error::new(std::io::ErrorKind::NotFound, "file not found"));
+        let data = rollbar_format!(Error error_chain = err, context = "config#load");
+        assert_eq!(data.level, Some(Level::Error));
+        assert_eq!(data.context, Some("config#load".to_string()));
+
+        match data.body {
+            crate::types::Body::TraceChainBody { trace_chain, .. } => {
+                assert_eq!(trace_chain.len(), 2);
+                assert_eq!(trace_chain[0].exception.message, Some("We could not load the configuration.".to_string()));
+                assert!(!trace_chain[0].frames.is_empty(), "the outer error should include a backtrace");
+                assert_eq!(trace_chain[1].exception.class, "std::io::error::Error");
+                assert_eq!(trace_chain[1].exception.message, Some("file not found".to_string()));
+            },
+            _ => panic!("Expected trace chain body")
+        }
+    }
+
+    #[test]
+    fn generate_error_chain_report_with_custom() {
+        let _lock = crate::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let err = std::io::Error::new(std::io::ErrorKind::NotFound, "file not found");
+        let data = rollbar_format!(error_chain = err, custom = map!{ owner: "Bob" });
+        assert_eq!(data.custom.as_ref().map(|c| c["owner"].clone()), Some(serde_json::json!("Bob")));
+
+        let item: crate::models::Item = (data, &crate::Configuration::default()).into();
+        match item.data.body {
+            crate::types::Body::TraceChainBody { trace_chain, .. } => {
+                let frames = &trace_chain[0].frames;
+                assert!(frames.iter().any(|frame| frame.method.is_some()), "the backtrace should be kept when custom data is provided");
+                assert!(!frames.iter().any(|frame| frame.filename == crate::helpers::UNRESOLVED_FRAME), "the backtrace should be symbolicated");
+                assert_eq!(frames.last().map(|frame| frame.filename.clone()), Some(file!().to_string()), "the call site should be the last frame");
+            },
+            _ => panic!("Expected trace chain body")
+        }
+    }
+
+    #[test]
+    fn generate_ctx_report() {
+        let user_id = 42;
+        let order_id = "ord_123";
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+
+        let data = __rollbar_ctx_format!(Error error = err, capture: [user_id, order_id], context = "orders#create");
+        assert_eq!(data.level, Some(Level::Error));
+        assert_eq!(data.context, Some("orders#create".to_string()));
+
+        let custom = data.custom.expect("the captured variables should be stored in custom");
+        assert_eq!(custom["user_id"], "42");
+        assert_eq!(custom["order_id"], "\"ord_123\"");
+
+        rollbar_ctx!(message = "Order created", capture: [user_id, order_id]);
+    }
+
+    #[test]
+    fn resolve_levels() {
+        assert_eq!(__rollbar_level!(Critical), Level::Critical);
+        assert_eq!(__rollbar_level!(Error), Level::Error);
+        assert_eq!(__rollbar_level!(Warning), Level::Warning);
+        assert_eq!(__rollbar_level!(Info), Level::Info);
+        assert_eq!(__rollbar_level!(Debug), Level::Debug);
+    }
+
+    #[test]
+    fn generate_kv_report() {
+        let data = __rollbar_kv_format!(Warning message = "Payment declined", user_id = 42, environment = "eu-west"; environment = "production", context = "billing#charge");
+        assert_eq!(data.level, Some(Level::Warning));
+        assert_eq!(data.environment, Some("production".to_string()), "reserved keys should set the event's fields");
+        assert_eq!(data.context, Some("billing#charge".to_string()));
+
+        match data.body {
+            crate::types::Body::MessageBody { message, .. } => {
+                assert_eq!(message.body, "Payment declined");
+                assert_eq!(message.extra, map!{ user_id: 42, environment: "eu-west" });
+            },
+            _ => panic!("Expected message body")
+        }
+
+        rollbar_kv!(message = "User signed in", user_id = 42);
+        rollbar_kv!(Debug message = "Cache miss");
+    }
+
+    #[test]
+    fn generate_kv_report_with_expressions() {
+        fn describe(order_id: &str) -> String {
+            format!("Order {} could not be shipped", order_id)
+        }
+
+        let order_id = "ord_123";
+        let data = __rollbar_kv_format!(Error message = format!("Order {} failed", order_id), user_id = 42);
+        assert_eq!(data.level, Some(Level::Error));
+        assert_eq!(data.message_text(), Some("Order ord_123 failed"));
+
+        let data = __rollbar_kv_format!(Warning message = describe(order_id), order_id = order_id);
+        assert_eq!(data.level, Some(Level::Warning));
+        assert_eq!(data.message_text(), Some("Order ord_123 could not be shipped"));
+
+        match data.body {
+            crate::types::Body::MessageBody { message, .. } => assert_eq!(message.extra, map!{ order_id: "ord_123" }),
+            _ => panic!("Expected message body")
+        }
+
+        rollbar_kv!(message = format!("Order {} failed", order_id), user_id = 42);
+        rollbar_kv!(message = describe(order_id), order_id = order_id);
+        rollbar_kv!(Debug message = describe(order_id));
+    }
+
     #[test]
     fn test_handle_panics() {
         handle_panics!();
@@ -277,13 +785,42 @@ mod tests {
             crate::types::Body::TraceBody { trace, .. } => {
                 assert_eq!(trace.exception.class, "rollbar_rs::errors::Error");
                 assert_ne!(trace.exception.message, None);
-                assert_ne!(trace.exception.description, None);
 
                 assert!(trace.frames.len() > 0, "the trace should have at least one frame");
                 assert_eq!(trace.frames[trace.frames.len()-1].filename, file!().to_string());
             },
             _ => panic!("Unexpected trace type")
         }
+
+        let custom = data.custom.expect("the custom field should be populated");
+        assert!(custom["error_debug"].as_str().unwrap_or_default().contains("This is a test error."), "the error's debug representation should be included");
+    }
+
+    #[test]
+    fn generate_error_report_with_custom_keeps_error_debug() {
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+
+        let data = rollbar_format!(error = err, custom = map!{ owner: "Bob" });
+        let custom = data.custom.expect("the custom field should be populated");
+        assert_eq!(custom["owner"], "Bob");
+        assert!(custom["error_debug"].as_str().unwrap_or_default().contains("This is a test error."), "the error's debug representation should be kept");
+
+        let data = rollbar_format!(error_chain = err, custom = map!{ owner: "Bob" });
+        let custom = data.custom.expect("the custom field should be populated");
+        assert_eq!(custom["owner"], "Bob");
+        assert!(custom["error_debug"].as_str().unwrap_or_default().contains("This is a test error."), "the error's debug representation should be kept");
+    }
+
+    #[test]
+    fn generate_report_with_uuid() {
+        let msg = rollbar_format!(message = "Hello, world!", uuid = "my-custom-uuid");
+        let item: crate::models::Item = (msg, &Configuration::default()).into();
+        assert_eq!(item.data.uuid, Some("my-custom-uuid".to_owned()));
+
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+        let data = rollbar_format!(Error error = err, uuid = "my-other-uuid");
+        let item: crate::models::Item = (data, &Configuration::default()).into();
+        assert_eq!(item.data.uuid, Some("my-other-uuid".to_owned()));
     }
 
     #[test]
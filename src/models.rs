@@ -1,11 +1,108 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::Configuration;
 
-#[derive(Debug, Serialize, Deserialize, Default)]
-#[serde(default)]
+/// The key within an event's `custom` data under which fields attached using
+/// [`crate::helpers::attach_extra_data`] are held until the event is prepared.
+pub(crate) const EXTRA_DATA_KEY: &str = "__rollbar_extra_data";
+
+/// Gets the provided event without any fields attached using [`crate::helpers::attach_extra_data`],
+/// so that they are not exposed (as part of its `custom` data) to hooks which are
+/// called before the event is prepared.
+pub(crate) fn without_extra_data(data: &rollbar_rust::types::Data) -> std::borrow::Cow<'_, rollbar_rust::types::Data> {
+    if !data.custom.as_ref().map(|custom| custom.contains_key(EXTRA_DATA_KEY)).unwrap_or_default() {
+        return std::borrow::Cow::Borrowed(data);
+    }
+
+    let mut data = data.clone();
+    take_extra_data(&mut data);
+    std::borrow::Cow::Owned(data)
+}
+
+/// Removes the fields attached using [`crate::helpers::attach_extra_data`] from the
+/// provided event's `custom` data, returning them.
+fn take_extra_data(data: &mut rollbar_rust::types::Data) -> serde_json::Map<String, serde_json::Value> {
+    let extra_data = match data.custom.as_mut().and_then(|custom| custom.remove(EXTRA_DATA_KEY)) {
+        Some(serde_json::Value::Object(extra_data)) => extra_data,
+        _ => Default::default(),
+    };
+
+    if data.custom.as_ref().map(|custom| custom.is_empty()).unwrap_or_default() {
+        data.custom = None;
+    }
+
+    extra_data
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Item {
     pub data: rollbar_rust::types::Data,
+
+    /// The version of the payload schema emitted by this crate, allowing the
+    /// shape of the payload to be identified by tools which process it.
+    pub schema_version: u32,
+
+    /// Top-level fields which are not modelled by `Data`, and which are merged
+    /// into it when this item is serialized. Fields which are set on `data` take
+    /// precedence over these. Fields which are not modelled by `Data` are restored
+    /// here when an item is deserialized (for example, when it is replayed from
+    /// the spool).
+    pub extra_data: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Serialize for Item {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Payload<D: Serialize> {
+            data: D,
+            schema_version: u32,
+        }
+
+        if self.extra_data.is_empty() {
+            return Payload { data: &self.data, schema_version: self.schema_version }.serialize(serializer);
+        }
+
+        let mut data = serde_json::to_value(&self.data).map_err(serde::ser::Error::custom)?;
+        if let serde_json::Value::Object(fields) = &mut data {
+            for (key, value) in self.extra_data.iter() {
+                match fields.get(key) {
+                    None | Some(serde_json::Value::Null) => { fields.insert(key.clone(), value.clone()); },
+                    Some(_) => {},
+                }
+            }
+        }
+
+        Payload { data, schema_version: self.schema_version }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Item {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize, Default)]
+        #[serde(default)]
+        struct Payload {
+            data: Option<serde_json::Map<String, serde_json::Value>>,
+            schema_version: u32,
+        }
+
+        let payload = Payload::deserialize(deserializer)?;
+        let fields = match payload.data {
+            Some(fields) => fields,
+            None => return Ok(Item { schema_version: payload.schema_version, ..Default::default() }),
+        };
+
+        let data: rollbar_rust::types::Data = serde_json::from_value(serde_json::Value::Object(fields.clone()))
+            .map_err(serde::de::Error::custom)?;
+
+        // Any fields which were not populated on `data` must have been merged in from
+        // `extra_data` when the item was serialized, so they are restored there.
+        let modelled = serde_json::to_value(&data).map_err(serde::de::Error::custom)?;
+        let extra_data = fields.into_iter()
+            .filter(|(key, value)| !value.is_null() && modelled.get(key).map(|field| field.is_null()).unwrap_or(true))
+            .collect();
+
+        Ok(Item { data, schema_version: payload.schema_version, extra_data })
+    }
 }
 
 /// Updates an object's fields with those from another object, or with
@@ -38,19 +135,627 @@ impl From<(rollbar_rust::types::Data, &Configuration)> for Item {
     fn from((data, config): (rollbar_rust::types::Data, &Configuration)) -> Self {
         let mut data = data;
 
+        if config.capture_backtrace {
+            crate::helpers::resolve_backtrace(&mut data);
+        } else {
+            crate::helpers::discard_backtrace(&mut data);
+        }
+
+        let extra_data = take_extra_data(&mut data);
+
+        crate::helpers::map_level(&mut data, config);
         set_default!(data[level] = crate::Level::Info);
         set_default!(data[language] = "rust".to_string());
 
         set_default!(data[environment] from config);
         set_default!(data[code_version] from config);
+        if config.detect_platform && data.platform.is_none() {
+            data.platform = crate::helpers::detect_platform();
+        }
+
         set_default!(data[platform] from config);
         set_default!(data[framework] from config);
+        if data.context.is_none() {
+            data.context = config.context_fn.as_ref().and_then(|context_fn| context_fn());
+        }
+
+        #[cfg(feature = "tracing")]
+        if data.context.is_none() {
+            data.context = tracing::Span::current().metadata().map(|span| span.name().to_string());
+        }
+
+        #[cfg(feature = "tracing")]
+        {
+            let span_fields = crate::span_fields::current_span_fields();
+            if !span_fields.is_empty() {
+                let custom = data.custom.get_or_insert_with(Default::default);
+                for (key, value) in span_fields {
+                    custom.entry(key).or_insert(value);
+                }
+            }
+        }
+
         set_default!(data[context] from config);
-        set_default!(data[custom] from config);
+
+        // Events may already carry custom data added by this crate (like `error_debug`),
+        // so the configured custom data is merged into it, with the event's keys winning.
+        if let Some(config_custom) = &config.custom {
+            let custom = data.custom.get_or_insert_with(Default::default);
+            for (key, value) in config_custom {
+                custom.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+
+        if let Some(request_id) = crate::scope::current_request_id() {
+            data.custom.get_or_insert_with(Default::default)
+                .entry("request_id".to_string())
+                .or_insert_with(|| request_id.into());
+        }
+
+        if config.capture_thread {
+            let thread = std::thread::current();
+            let custom = data.custom.get_or_insert_with(Default::default);
+            if let Some(name) = thread.name() {
+                custom.entry("thread_name".to_string()).or_insert_with(|| name.into());
+            }
+
+            custom.entry("thread_id".to_string()).or_insert_with(|| format!("{:?}", thread.id()).into());
+        }
+
+        if let Some(capture_env) = &config.capture_env {
+            let env: serde_json::Map<String, serde_json::Value> = capture_env.iter()
+                .filter_map(|name| std::env::var(name).ok().map(|value| {
+                    if config.is_sensitive_field(name) {
+                        (name.clone(), "***".into())
+                    } else {
+                        (name.clone(), value.into())
+                    }
+                }))
+                .collect();
+
+            data.custom.get_or_insert_with(Default::default)
+                .entry("env".to_string())
+                .or_insert_with(|| env.into());
+        }
+
+        #[cfg(feature = "otel")]
+        crate::helpers::attach_otel_context(&mut data);
+
+        if let Some(url) = data.request.as_mut().and_then(|request| request.url.as_mut()) {
+            *url = crate::helpers::redact_url_query(url, config);
+        }
+
+        if let (Some(prefix), rollbar_rust::types::Body::MessageBody { message, .. }) = (&config.message_prefix, &mut data.body) {
+            if !message.body.starts_with(prefix.as_str()) {
+                message.body = format!("{}{}", prefix, message.body);
+            }
+        }
+
+        if let Some(max_frames) = config.max_frames {
+            let truncated: usize = match &mut data.body {
+                rollbar_rust::types::Body::TraceBody { trace, .. } => truncate_frames(trace, max_frames),
+                rollbar_rust::types::Body::TraceChainBody { trace_chain, .. } => trace_chain.iter_mut().map(|trace| truncate_frames(trace, max_frames)).sum(),
+                _ => 0,
+            };
+
+            if truncated > 0 {
+                data.custom.get_or_insert_with(Default::default)
+                    .insert("frames_truncated".to_string(), truncated.into());
+            }
+        }
+
+        // The allow-list is applied once all of the custom data (including that added
+        // by this crate) has been attached, so that nothing unexpected is sent.
+        if let (Some(allowed), Some(custom)) = (&config.allowed_custom_keys, &mut data.custom) {
+            custom.retain(|key, _| allowed.contains(key));
+        }
+
+        #[cfg(feature = "source-context")]
+        if let rollbar_rust::types::Body::TraceBody { trace, .. } = &mut data.body {
+            crate::helpers::populate_source_context(&mut trace.frames);
+        }
+
+        if data.fingerprint.is_none() {
+            data.fingerprint = config.fingerprint_fn.as_ref().and_then(|fingerprint_fn| fingerprint_fn(&data));
+        }
+
+        if config.auto_fingerprint && data.fingerprint.is_none() {
+            if let rollbar_rust::types::Body::TraceBody { trace, .. } = &data.body {
+                data.fingerprint = Some(crate::helpers::get_fingerprint(trace));
+            }
+        }
+
+        if data.server.is_none() && config.host.is_some() {
+            data.server = Some(rollbar_rust::types::Server {
+                host: config.host.clone(),
+                root: None,
+                branch: None,
+                code_version: None,
+            });
+        }
+
+        if let Some(server) = &mut data.server {
+            crate::helpers::populate_server_git_info(server);
+        }
 
         set_default!(data[platform] = std::env::consts::OS.to_string());
+        if config.deterministic_uuid && data.uuid.is_none() {
+            data.uuid = Some(crate::helpers::content_uuid(&data));
+        }
+
         set_default!(data[uuid] = crate::helpers::new_uuid());
+        set_default!(data[timestamp] = crate::helpers::now_timestamp());
 
-        Item { data }
+        Item { data, schema_version: crate::SCHEMA_VERSION, extra_data }
     }
-}
\ No newline at end of file
+}
+
+/// Truncates a trace to the provided number of frames, returning the number of
+/// frames which were removed.
+/// 
+/// Rollbar orders frames with the most recent call last, so the frames at the end
+/// of the trace (which are the most relevant) are kept.
+fn truncate_frames(trace: &mut rollbar_rust::types::Trace, max_frames: usize) -> usize {
+    let omitted = trace.frames.len().saturating_sub(max_frames);
+    if omitted > 0 {
+        trace.frames.drain(..omitted);
+    }
+
+    omitted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_timestamp() {
+        let item: Item = (crate::rollbar_format!(message = "Test message", timestamp = 1_600_000_000u64), &Configuration::default()).into();
+        assert_eq!(item.data.timestamp, Some(1_600_000_000));
+
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &Configuration::default()).into();
+        assert!(item.data.timestamp.unwrap_or_default() > 1_600_000_000, "the current time should be used by default");
+    }
+
+    #[test]
+    fn test_extra_data() {
+        let data = crate::rollbar_format!(message = "Test message", environment = "production", extra_data = crate::map!{
+            attributes: ["beta"],
+            environment: "staging",
+            custom: { "overridden": true }
+        });
+
+        let item: Item = (data, &Configuration::default()).into();
+        assert!(item.data.custom.is_none(), "extra data should not be reported as custom data");
+
+        let json = serde_json::to_value(&item).unwrap();
+        assert_eq!(json["data"]["attributes"], serde_json::json!(["beta"]), "unmodelled fields should be included in the payload");
+        assert_eq!(json["data"]["environment"], "production", "typed fields should take precedence over extra data");
+        assert_eq!(json["data"]["custom"], serde_json::json!({ "overridden": true }), "extra data may populate unset fields");
+        assert_eq!(json["schema_version"], crate::SCHEMA_VERSION);
+
+        let replayed: Item = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(replayed.extra_data.get("attributes"), Some(&serde_json::json!(["beta"])), "unmodelled fields should be restored when an item is replayed");
+        assert_eq!(serde_json::to_value(&replayed).unwrap(), json, "replayed items should produce the same payload");
+
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &Configuration::default()).into();
+        let json = serde_json::to_value(&item).unwrap();
+        assert!(json["data"].get("attributes").is_none());
+    }
+
+    #[test]
+    fn test_max_frames() {
+        fn recurse(depth: usize) -> rollbar_rust::types::Data {
+            if depth == 0 {
+                let err = crate::errors::user("This is a test error.", "Try not crashing.");
+                crate::rollbar_format!(error = err)
+            } else {
+                recurse(depth - 1)
+            }
+        }
+
+        let data = recurse(50);
+
+        // Backtraces are only symbolicated when the item is prepared, so the full set of
+        // frames is taken from an item which has not had its frames limited.
+        let full: Item = (data.clone(), &Configuration { max_frames: None, ..Default::default() }).into();
+        let frame_count = match &full.data.body {
+            rollbar_rust::types::Body::TraceBody { trace, .. } => trace.frames.len(),
+            _ => panic!("Expected trace body"),
+        };
+        let last_frame = match &full.data.body {
+            rollbar_rust::types::Body::TraceBody { trace, .. } => trace.frames.last().cloned(),
+            _ => None,
+        };
+
+        let config = Configuration {
+            max_frames: Some(10),
+            ..Default::default()
+        };
+
+        let item: Item = (data.clone(), &config).into();
+        match &item.data.body {
+            rollbar_rust::types::Body::TraceBody { trace, .. } => {
+                assert_eq!(trace.frames.len(), 10);
+                assert_eq!(trace.frames.last().map(|f| &f.filename), last_frame.as_ref().map(|f| &f.filename), "the most recent frames should be kept");
+                assert!(trace.frames[..9].iter().all(|f| f.method.as_deref().is_some_and(|m| m.contains("recurse"))), "the oldest frames should be dropped");
+            },
+            _ => panic!("Expected trace body"),
+        }
+
+        assert_eq!(item.data.custom.unwrap()["frames_truncated"], frame_count - 10);
+
+        let item: Item = (data, &Configuration { max_frames: None, ..Default::default() }).into();
+        assert!(item.data.custom.map(|c| !c.contains_key("frames_truncated")).unwrap_or(true));
+    }
+
+    #[test]
+    fn test_code_version() {
+        let config = Configuration {
+            code_version: Some("1.0.0".to_string()),
+            ..Default::default()
+        };
+
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &config).into();
+        assert_eq!(item.data.code_version, Some("1.0.0".to_string()));
+
+        let item: Item = (crate::rollbar_format!(message = "Test message", code_version = "1.1.0-canary"), &config).into();
+        assert_eq!(item.data.code_version, Some("1.1.0-canary".to_string()), "the per-event code version should take precedence");
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn test_tracing_span_context() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(crate::SpanFieldsLayer), || {
+            let span = tracing::info_span!("handle_request", user_id = 42, route = "/orders");
+            let _entered = span.enter();
+
+            let item: Item = (crate::rollbar_format!(message = "Test message"), &Configuration::default()).into();
+            assert_eq!(item.data.context, Some("handle_request".to_string()));
+            let custom = item.data.custom.expect("the span's fields should be included in the custom data");
+            assert_eq!(custom["user_id"], 42);
+            assert_eq!(custom["route"], "/orders");
+
+            let item: Item = (crate::rollbar_format!(message = "Test message", context = "explicit#context"), &Configuration::default()).into();
+            assert_eq!(item.data.context, Some("explicit#context".to_string()));
+
+            let inner = tracing::info_span!("load_order", route = "/orders/:id", order_id = tracing::field::Empty);
+            let _inner = inner.enter();
+            inner.record("order_id", "ord_123");
+
+            let item: Item = (crate::rollbar_format!(message = "Test message", custom = crate::map!{ user_id: 7 }), &Configuration::default()).into();
+            assert_eq!(item.data.context, Some("load_order".to_string()));
+
+            let custom = item.data.custom.expect("the span's fields should be included in the custom data");
+            assert_eq!(custom["user_id"], 7, "the event's custom data should take precedence over span fields");
+            assert_eq!(custom["route"], "/orders/:id", "the fields of inner spans should take precedence");
+            assert_eq!(custom["order_id"], "ord_123", "fields recorded after the span was created should be included");
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "otel")]
+    fn test_otel_context() {
+        use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+
+        let span_context = SpanContext::new(
+            TraceId::from_u128(0x4bf92f3577b34da6a3ce929d0e0e4736),
+            SpanId::from_u64(0x00f067aa0ba902b7),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+
+        let _guard = opentelemetry::Context::current().with_remote_span_context(span_context).attach();
+
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+        let item: Item = (crate::rollbar_format!(error = err), &Configuration::default()).into();
+        let custom = item.data.custom.expect("the event should have custom data");
+        assert_eq!(custom["trace_id"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(custom["span_id"], "00f067aa0ba902b7");
+    }
+
+    #[test]
+    fn test_framework() {
+        let config = Configuration {
+            framework: Some("axum".to_string()),
+            ..Default::default()
+        };
+
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &config).into();
+        assert_eq!(item.data.framework, Some("axum".to_string()));
+
+        let item: Item = (crate::rollbar_format!(message = "Test message", framework = "worker"), &config).into();
+        assert_eq!(item.data.framework, Some("worker".to_string()), "the per-event framework should take precedence");
+    }
+
+    #[test]
+    fn test_fingerprint_fn() {
+        let config = Configuration {
+            fingerprint_fn: Some(Arc::new(|data: &rollbar_rust::types::Data| match &data.body {
+                rollbar_rust::types::Body::MessageBody { message, .. } => message.body.split(':').next().map(|prefix| prefix.to_string()),
+                _ => None,
+            })),
+            ..Default::default()
+        };
+
+        let item: Item = (crate::rollbar_format!(message = "Query failed: SELECT * FROM users"), &config).into();
+        assert_eq!(item.data.fingerprint, Some("Query failed".to_string()));
+
+        let item: Item = (crate::rollbar_format!(message = "Query failed: SELECT * FROM users", fingerprint = "explicit"), &config).into();
+        assert_eq!(item.data.fingerprint, Some("explicit".to_string()), "an explicit fingerprint should take precedence");
+    }
+
+    #[test]
+    fn test_request_url_redaction() {
+        use crate::RequestExt;
+
+        let mut data = crate::rollbar_format!(message = "Test message");
+        data.request = Some(crate::Request::new("GET", "https://example.com/search?token=abc&page=2#results"));
+
+        let item: Item = (data, &Configuration::default()).into();
+        assert_eq!(item.data.request.and_then(|r| r.url), Some("https://example.com/search?token=***&page=2#results".to_string()));
+    }
+
+    #[test]
+    fn test_config_custom() {
+        let config = Configuration {
+            custom: Some(crate::map!{ service: "api", region: "eu" }),
+            ..Default::default()
+        };
+
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+        let item: Item = (crate::rollbar_format!(error = err, custom = crate::map!{ service: "web" }), &config).into();
+
+        let custom = item.data.custom.expect("the custom field should be populated");
+        assert_eq!(custom["service"], "web", "the event's custom data should take precedence");
+        assert_eq!(custom["region"], "eu", "the configured custom data should be merged in");
+        assert!(custom.contains_key("error_debug"));
+    }
+
+    #[test]
+    fn test_allowed_custom_keys() {
+        let config = Configuration {
+            custom: Some(crate::map!{ service: "api", region: "eu" }),
+            allowed_custom_keys: Some(vec!["service".to_string()]),
+            ..Default::default()
+        };
+
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &config).into();
+        assert_eq!(item.data.custom, Some(crate::map!{ service: "api" }));
+
+        let item: Item = (crate::rollbar_format!(message = "Test message", custom = crate::map!{ service: "web", user_email: "jdoe@example.com" }), &config).into();
+        assert_eq!(item.data.custom, Some(crate::map!{ service: "web" }));
+
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &Configuration { allowed_custom_keys: None, ..config }).into();
+        assert_eq!(item.data.custom, Some(crate::map!{ service: "api", region: "eu" }), "custom keys should be passed through without an allow-list");
+    }
+
+    #[test]
+    fn test_allowed_custom_keys_with_truncated_frames() {
+        let data = rollbar_rust::types::Data {
+            body: rollbar_rust::types::Body::TraceBody {
+                telemetry: None,
+                trace: rollbar_rust::types::Trace {
+                    exception: rollbar_rust::types::Exception {
+                        class: "TestError".to_string(),
+                        ..Default::default()
+                    },
+                    frames: vec![Default::default(); 5],
+                },
+            },
+            ..Default::default()
+        };
+
+        let config = Configuration {
+            custom: Some(crate::map!{ service: "api" }),
+            allowed_custom_keys: Some(vec!["service".to_string()]),
+            max_frames: Some(2),
+            ..Default::default()
+        };
+
+        let item: Item = (data.clone(), &config).into();
+        assert_eq!(item.data.custom, Some(crate::map!{ service: "api" }), "keys added while preparing the event should respect the allow-list");
+
+        let config = Configuration {
+            allowed_custom_keys: Some(vec!["service".to_string(), "frames_truncated".to_string()]),
+            ..config
+        };
+
+        let item: Item = (data, &config).into();
+        assert_eq!(item.data.custom, Some(crate::map!{ service: "api", frames_truncated: 3 }));
+    }
+
+    #[test]
+    fn test_capture_env() {
+        let _lock = crate::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let previous: Vec<(&str, Option<std::ffi::OsString>)> = ["RUST_LOG", "ROLLBAR_TEST_SECRET", "ROLLBAR_TEST_UNLISTED"].into_iter()
+            .map(|name| (name, std::env::var_os(name)))
+            .collect();
+
+        std::env::set_var("RUST_LOG", "info");
+        std::env::set_var("ROLLBAR_TEST_SECRET", "super_secret");
+        std::env::set_var("ROLLBAR_TEST_UNLISTED", "unlisted");
+
+        let config = Configuration {
+            capture_env: Some(vec!["RUST_LOG".to_string(), "ROLLBAR_TEST_SECRET".to_string()]),
+            ..Default::default()
+        };
+
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &config).into();
+
+        for (name, value) in previous {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+
+        let env = &item.data.custom.unwrap()["env"];
+        assert_eq!(env["RUST_LOG"], "info");
+        assert_eq!(env["ROLLBAR_TEST_SECRET"], "***", "sensitive variables should be redacted");
+        assert!(env.get("ROLLBAR_TEST_UNLISTED").is_none(), "unlisted variables should not be captured");
+
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &Configuration::default()).into();
+        assert!(item.data.custom.map(|c| !c.contains_key("env")).unwrap_or(true), "no variables should be captured by default");
+    }
+
+    #[test]
+    fn test_capture_thread() {
+        let config = Configuration {
+            capture_thread: true,
+            ..Default::default()
+        };
+
+        let item: Item = std::thread::Builder::new().name("worker-1".to_string()).spawn(move || {
+            (crate::rollbar_format!(message = "Test message"), &config).into()
+        }).unwrap().join().unwrap();
+
+        let custom = item.data.custom.unwrap();
+        assert_eq!(custom["thread_name"], "worker-1");
+        assert!(custom["thread_id"].as_str().unwrap().starts_with("ThreadId("));
+
+        let config = Configuration {
+            capture_thread: true,
+            ..Default::default()
+        };
+
+        let item: Item = (crate::rollbar_format!(message = "Test message", custom = crate::map!{ thread_name: "explicit" }), &config).into();
+        assert_eq!(item.data.custom.unwrap()["thread_name"], "explicit", "user provided values should not be overwritten");
+
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &Configuration::default()).into();
+        assert!(item.data.custom.map(|c| !c.contains_key("thread_id")).unwrap_or(true), "threads should not be captured by default");
+    }
+
+    #[test]
+    fn test_server_git_info() {
+        let _lock = crate::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let previous: Vec<(&str, Option<std::ffi::OsString>)> = ["GIT_BRANCH", "GIT_COMMIT"].into_iter()
+            .map(|name| (name, std::env::var_os(name)))
+            .collect();
+
+        std::env::set_var("GIT_BRANCH", "main");
+        std::env::set_var("GIT_COMMIT", "abc123");
+
+        let config = Configuration {
+            host: Some("web-1".to_string()),
+            ..Default::default()
+        };
+
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &config).into();
+        let server = item.data.server.unwrap();
+        assert_eq!(server.host, Some("web-1".to_string()));
+        assert_eq!(server.branch, Some("main".to_string()));
+        assert_eq!(server.code_version, Some("abc123".to_string()));
+
+        let server = crate::types::Server {
+            host: Some("web-2".to_string()),
+            root: None,
+            branch: Some("release".to_string()),
+            code_version: None,
+        };
+
+        let item: Item = (crate::rollbar_format!(message = "Test message", server = server), &config).into();
+        let server = item.data.server.unwrap();
+        assert_eq!(server.host, Some("web-2".to_string()));
+        assert_eq!(server.branch, Some("release".to_string()), "per-event values should take precedence");
+        assert_eq!(server.code_version, Some("abc123".to_string()));
+
+        for (name, value) in previous {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+    }
+
+    #[test]
+    fn test_message_prefix() {
+        let config = Configuration {
+            message_prefix: Some("[billing] ".to_string()),
+            ..Default::default()
+        };
+
+        let item: Item = (crate::rollbar_format!(message = "Invoice generated"), &config).into();
+        match &item.data.body {
+            rollbar_rust::types::Body::MessageBody { message, .. } => assert_eq!(message.body, "[billing] Invoice generated"),
+            _ => panic!("Expected message body"),
+        }
+
+        let item: Item = (item.data, &config).into();
+        match &item.data.body {
+            rollbar_rust::types::Body::MessageBody { message, .. } => assert_eq!(message.body, "[billing] Invoice generated", "messages should only be prefixed once"),
+            _ => panic!("Expected message body"),
+        }
+
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+        let item: Item = (crate::rollbar_format!(error = err), &config).into();
+        match &item.data.body {
+            rollbar_rust::types::Body::TraceBody { trace, .. } => assert!(!trace.exception.message.as_deref().unwrap_or_default().starts_with("[billing]"), "errors should not be prefixed"),
+            _ => panic!("Expected trace body"),
+        }
+    }
+
+    #[test]
+    fn test_deterministic_uuid() {
+        let config = Configuration {
+            deterministic_uuid: true,
+            ..Default::default()
+        };
+
+        let first: Item = (crate::rollbar_format!(Error message = "Deployment failed"), &config).into();
+        let second: Item = (crate::rollbar_format!(Error message = "Deployment failed"), &config).into();
+        assert_eq!(first.data.uuid, second.data.uuid, "identical events should have the same uuid");
+
+        let first: Item = (crate::rollbar_format!(Error message = "Deployment failed"), &Configuration::default()).into();
+        let second: Item = (crate::rollbar_format!(Error message = "Deployment failed"), &Configuration::default()).into();
+        assert_ne!(first.data.uuid, second.data.uuid, "uuids should be random by default");
+    }
+
+    #[test]
+    fn test_schema_version() {
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &Configuration::default()).into();
+        let json = serde_json::to_value(&item).unwrap();
+        assert_eq!(json["schema_version"], crate::SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_auto_fingerprint() {
+        let config = Configuration {
+            auto_fingerprint: true,
+            ..Default::default()
+        };
+
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+        let item: Item = (crate::rollbar_format!(error = err), &config).into();
+        assert!(item.data.fingerprint.is_some(), "a fingerprint should be computed for errors");
+
+        let err = crate::errors::user("This is a test error.", "Try not crashing.");
+        let item: Item = (crate::rollbar_format!(error = err, fingerprint = "explicit"), &config).into();
+        assert_eq!(item.data.fingerprint, Some("explicit".to_string()));
+
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &config).into();
+        assert_eq!(item.data.fingerprint, None, "messages should not be fingerprinted");
+    }
+
+    #[test]
+    fn test_context_fn() {
+        let config = Configuration {
+            context: Some("static#context".to_string()),
+            context_fn: Some(Arc::new(|| Some("dynamic#context".to_string()))),
+            ..Default::default()
+        };
+
+        let item: Item = (crate::rollbar_format!(message = "Test message"), &config).into();
+        assert_eq!(item.data.context, Some("dynamic#context".to_string()));
+
+        let item: Item = (crate::rollbar_format!(message = "Test message", context = "explicit#context"), &config).into();
+        assert_eq!(item.data.context, Some("explicit#context".to_string()));
+    }
+}
@@ -0,0 +1,36 @@
+/// The outcome of attempting to report an event to Rollbar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReportOutcome {
+    /// The event was handed to the transport to be sent to Rollbar.
+    Queued {
+        /// The unique identifier assigned to the event.
+        uuid: String,
+    },
+
+    /// The event was dropped before it was handed to the transport.
+    Dropped(DropReason),
+}
+
+impl ReportOutcome {
+    /// Determines whether the event was queued for sending to Rollbar.
+    pub fn is_queued(&self) -> bool {
+        matches!(self, ReportOutcome::Queued { .. })
+    }
+}
+
+/// The reason that an event was not sent to Rollbar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DropReason {
+    /// The event's level was below the configured minimum log level.
+    BelowLogLevel,
+
+    /// No access token has been configured.
+    NoToken,
+
+    /// Reporting has been disabled using `set_enabled(false)`.
+    Disabled,
+
+    /// The event exceeded the configured `max_events_per_window`.
+    RateLimited,
+}
@@ -0,0 +1,149 @@
+use std::{sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}, time::{Duration, Instant}};
+
+use crate::clock::{Clock, SystemClock};
+
+/// A token bucket which limits the number of events reported within a window of
+/// time, protecting your Rollbar quota from floods of events.
+/// 
+/// The limit is provided each time an event is admitted so that changes to the
+/// configuration take effect immediately. Events which are dropped are tallied,
+/// and the tally is handed back (at most once per window) when an event is next
+/// admitted so that a summary of the suppressed events can be reported.
+#[derive(Debug)]
+pub (in crate) struct RateLimiter {
+    clock: Arc<dyn Clock>,
+    state: Mutex<LimiterState>,
+    dropped: AtomicU64,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    tokens: f64,
+    refilled_at: Option<Instant>,
+    pending: u64,
+    summarized_at: Option<Instant>,
+}
+
+/// The outcome of attempting to admit an event through the rate limiter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub (in crate) enum RateLimit {
+    /// The event may be reported. If events were dropped since the last summary,
+    /// the number which were dropped is provided so that it may be reported.
+    Allowed { suppressed: Option<u64> },
+
+    /// The event exceeded the rate limit and should be dropped.
+    Exceeded,
+}
+
+impl RateLimiter {
+    /// Creates a new rate limiter which measures its windows using the provided clock.
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            state: Mutex::new(LimiterState::default()),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Attempts to admit an event, allowing at most `max_events` events within
+    /// each `window`.
+    pub fn admit(&self, max_events: u32, window: Duration) -> RateLimit {
+        let now = self.clock.now();
+        let capacity = max_events as f64;
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.tokens = match state.refilled_at {
+            Some(refilled_at) if !window.is_zero() => {
+                let refill = now.duration_since(refilled_at).as_secs_f64() / window.as_secs_f64() * capacity;
+                (state.tokens + refill).min(capacity)
+            },
+            _ => capacity,
+        };
+        state.refilled_at = Some(now);
+
+        if state.tokens < 1.0 {
+            state.pending += 1;
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return RateLimit::Exceeded;
+        }
+
+        state.tokens -= 1.0;
+
+        let summary_due = state.summarized_at.map(|at| now.duration_since(at) >= window).unwrap_or(true);
+        if state.pending > 0 && summary_due {
+            state.summarized_at = Some(now);
+            let suppressed = std::mem::take(&mut state.pending);
+            return RateLimit::Allowed { suppressed: Some(suppressed) };
+        }
+
+        RateLimit::Allowed { suppressed: None }
+    }
+
+    /// Gets the total number of events which have been dropped by this limiter.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Builds the event which summarizes the events dropped by the rate limiter.
+pub (in crate) fn summary(suppressed: u64, max_events: u32, window: Duration) -> crate::types::Data {
+    crate::rollbar_format!(Warning message = format!("{} events were dropped because they exceeded the limit of {} events every {:?}.", suppressed, max_events, window), custom = crate::map!{
+        rate_limited: suppressed
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::default();
+        let window = Duration::from_secs(60);
+
+        for _ in 0..3 {
+            assert_eq!(limiter.admit(3, window), RateLimit::Allowed { suppressed: None });
+        }
+
+        assert_eq!(limiter.admit(3, window), RateLimit::Exceeded);
+        assert_eq!(limiter.admit(3, window), RateLimit::Exceeded);
+        assert_eq!(limiter.dropped(), 2);
+    }
+
+    #[test]
+    fn test_rate_limiter_mock_clock() {
+        let clock = crate::MockClock::new();
+        let limiter = RateLimiter::new(Arc::new(clock.clone()));
+        let window = Duration::from_secs(60);
+
+        assert_eq!(limiter.admit(1, window), RateLimit::Allowed { suppressed: None });
+        assert_eq!(limiter.admit(1, window), RateLimit::Exceeded);
+
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(limiter.admit(1, window), RateLimit::Exceeded, "the window should not have expired yet");
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(limiter.admit(1, window), RateLimit::Allowed { suppressed: Some(2) }, "the window should expire once the clock is advanced");
+    }
+
+    #[test]
+    fn test_rate_limiter_refill() {
+        let limiter = RateLimiter::default();
+        let window = Duration::from_millis(100);
+
+        assert_eq!(limiter.admit(1, window), RateLimit::Allowed { suppressed: None });
+        assert_eq!(limiter.admit(1, window), RateLimit::Exceeded);
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(limiter.admit(1, window), RateLimit::Allowed { suppressed: Some(1) }, "the number of dropped events should be summarized");
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(limiter.admit(1, window), RateLimit::Allowed { suppressed: None }, "each dropped event should only be summarized once");
+    }
+}
@@ -0,0 +1,123 @@
+use std::cell::RefCell;
+
+thread_local! {
+    static CURRENT_CONTEXT: RefCell<Option<String>> = RefCell::new(None);
+}
+
+#[cfg(feature = "async")]
+tokio::task_local! {
+    static CURRENT_TASK_CONTEXT: Option<String>;
+}
+
+/// Runs the provided closure with the given request identifier attached to any
+/// events reported from within it on the current thread.
+/// 
+/// Events reported within the closure will have the request identifier added to
+/// their `custom.request_id` field (unless one has been set explicitly), allowing
+/// you to correlate errors with the request which caused them without needing to
+/// pass the identifier to every call site. Scopes may be nested, in which case the
+/// innermost request identifier is used.
+/// 
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// 
+/// with_context("req-1234", || {
+///     rollbar!(Error message = "Something went wrong while handling the request");
+/// });
+/// ```
+pub fn with_context<S: Into<String>, R, F: FnOnce() -> R>(request_id: S, f: F) -> R {
+    struct ScopeGuard(Option<String>);
+
+    impl Drop for ScopeGuard {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT_CONTEXT.with(|c| *c.borrow_mut() = previous);
+        }
+    }
+
+    let previous = CURRENT_CONTEXT.with(|c| c.borrow_mut().replace(request_id.into()));
+    let _guard = ScopeGuard(previous);
+
+    f()
+}
+
+/// Runs the provided future with the given request identifier attached to any
+/// events reported from within it.
+/// 
+/// This is the asynchronous equivalent of [`with_context`], and ensures that the
+/// request identifier follows the future across `.await` points (and between
+/// runtime worker threads) without leaking into other tasks.
+#[cfg(feature = "async")]
+pub async fn with_context_async<S: Into<String>, F: std::future::Future>(request_id: S, f: F) -> F::Output {
+    CURRENT_TASK_CONTEXT.scope(Some(request_id.into()), f).await
+}
+
+/// Gets the request identifier for the current scope, if one has been set.
+pub (in crate) fn current_request_id() -> Option<String> {
+    #[cfg(feature = "async")]
+    {
+        if let Ok(Some(request_id)) = CURRENT_TASK_CONTEXT.try_with(|c| c.clone()) {
+            return Some(request_id);
+        }
+    }
+
+    CURRENT_CONTEXT.with(|c| c.borrow().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_with_context() {
+        assert_eq!(current_request_id(), None);
+
+        with_context("req-1", || {
+            assert_eq!(current_request_id(), Some("req-1".to_string()));
+
+            with_context("req-2", || {
+                assert_eq!(current_request_id(), Some("req-2".to_string()));
+            });
+
+            let item: models::Item = (rollbar_format!(message = "Test message"), &Configuration::default()).into();
+            assert_eq!(item.data.custom.unwrap()["request_id"], "req-1");
+        });
+
+        assert_eq!(current_request_id(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_with_context_async() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            // Both tasks wait on the barrier within their scopes, so they are interleaved on
+            // the single runtime thread while each has its own request identifier set.
+            let barrier = std::sync::Arc::new(tokio::sync::Barrier::new(2));
+
+            let tasks: Vec<_> = ["req-1", "req-2"].into_iter().map(|request_id| {
+                let barrier = barrier.clone();
+                tokio::spawn(with_context_async(request_id, async move {
+                    assert_eq!(current_request_id(), Some(request_id.to_string()));
+
+                    barrier.wait().await;
+                    tokio::task::yield_now().await;
+                    assert_eq!(current_request_id(), Some(request_id.to_string()), "the request identifier should follow the task across await points");
+
+                    tokio::task::yield_now().await;
+                    current_request_id()
+                }))
+            }).collect();
+
+            for (task, request_id) in tasks.into_iter().zip(["req-1", "req-2"]) {
+                assert_eq!(task.await.unwrap(), Some(request_id.to_string()));
+            }
+
+            assert_eq!(current_request_id(), None, "the request identifier should not leak outside of its scope");
+        });
+
+        assert_eq!(current_request_id(), None);
+    }
+}
@@ -0,0 +1,98 @@
+use tracing::{field::{Field, Visit}, span::{Attributes, Id, Record}, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// A [`tracing_subscriber::Layer`] which records the fields of each `tracing` span,
+/// allowing events reported from within a span to include them in their `custom`
+/// data (unless the event has set those keys itself).
+///
+/// `tracing` does not retain the values of span fields, so this layer must be added
+/// to your subscriber for them to be reported. The name of the current span is used
+/// as the event's `context` with or without it.
+///
+/// # Example
+/// ```rust
+/// use rollbar_rs::*;
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// let subscriber = tracing_subscriber::registry().with(SpanFieldsLayer);
+/// tracing::subscriber::with_default(subscriber, || {
+///     let _span = tracing::info_span!("handle_request", user_id = 42).entered();
+///     rollbar!(Error message = "Something went wrong while handling the request");
+/// });
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SpanFieldsLayer;
+
+/// The fields which have been recorded on a span by the [`SpanFieldsLayer`].
+#[derive(Debug, Default)]
+struct SpanFields(serde_json::Map<String, serde_json::Value>);
+
+impl Visit for SpanFields {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanFieldsLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = SpanFields::default();
+            attrs.record(&mut fields);
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            match extensions.get_mut::<SpanFields>() {
+                Some(fields) => values.record(fields),
+                None => {
+                    let mut fields = SpanFields::default();
+                    values.record(&mut fields);
+                    extensions.insert(fields);
+                },
+            }
+        }
+    }
+}
+
+/// Gets the fields recorded by the [`SpanFieldsLayer`] on the current span and its
+/// parents, with the fields of inner spans taking precedence. This is empty when
+/// the current subscriber does not include the layer.
+pub (in crate) fn current_span_fields() -> serde_json::Map<String, serde_json::Value> {
+    let mut fields = serde_json::Map::new();
+
+    tracing::Span::current().with_subscriber(|(id, dispatch)| {
+        let span = dispatch.downcast_ref::<tracing_subscriber::Registry>().and_then(|registry| registry.span(id));
+        for span in span.into_iter().flat_map(|span| span.scope()) {
+            if let Some(span_fields) = span.extensions().get::<SpanFields>() {
+                for (key, value) in span_fields.0.iter() {
+                    fields.entry(key.clone()).or_insert_with(|| value.clone());
+                }
+            }
+        }
+    });
+
+    fields
+}
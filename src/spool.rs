@@ -0,0 +1,264 @@
+use std::{path::{Path, PathBuf}, sync::Arc, time::Duration};
+
+use crate::clock::{Clock, SystemClock};
+use crate::models::Item;
+use crate::errors::*;
+use crate::Error;
+
+/// A directory on disk in which events which could not be delivered to Rollbar
+/// are stored so that they can be replayed later.
+/// 
+/// Each event is stored in its own file, named using the event's `uuid`, which
+/// ensures that replaying an event multiple times will not result in duplicate
+/// entries in Rollbar. Events may optionally be compressed using gzip (and stored
+/// in `.json.gz` files) to reduce the space used by the spool, and both compressed
+/// and uncompressed events are replayed.
+#[derive(Debug, Clone)]
+pub struct Spool {
+    dir: PathBuf,
+    max_age: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    compress: bool,
+}
+
+impl Spool {
+    /// Creates a new spool which stores events in the provided directory.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into(), max_age: None, clock: Arc::new(SystemClock), compress: false }
+    }
+
+    /// Sets the maximum age of events which will be replayed from this spool, after
+    /// which they are considered stale and are discarded without being sent.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Sets whether events written to this spool are compressed using gzip, which
+    /// reduces the disk space used when many events are spooled.
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Sets the clock used to determine the age of spooled events.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Gets the directory in which events are spooled.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Writes the provided item to the spool, returning the path of the file it
+    /// was written to.
+    pub fn write(&self, item: &Item) -> Result<PathBuf, Error> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| user_with_internal(
+            "We could not create the directory used to spool Rollbar events.",
+            "Make sure that the spool directory you have configured is writable and try again.",
+            e
+        ))?;
+
+        let uuid = item.data.uuid.clone().unwrap_or_else(crate::helpers::new_uuid);
+        let extension = if self.compress { "json.gz" } else { "json" };
+        let path = self.dir.join(format!("{}.{}", uuid, extension));
+        let temp_path = self.dir.join(format!(".{}.{}.tmp", uuid, extension));
+
+        let mut body = serde_json::to_vec(item).map_err(|e| system_with_internal(
+            "We could not serialize a Rollbar event to write it to the spool.",
+            "Please report this issue to us on GitHub.",
+            e
+        ))?;
+
+        if self.compress {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            body = std::io::Write::write_all(&mut encoder, &body)
+                .and_then(|_| encoder.finish())
+                .map_err(|e| system_with_internal(
+                    "We could not compress a Rollbar event to write it to the spool.",
+                    "Please report this issue to us on GitHub.",
+                    e
+                ))?;
+        }
+
+        // Write to a temporary file first so that a partially written file is never replayed.
+        std::fs::write(&temp_path, body)
+            .and_then(|_| std::fs::rename(&temp_path, &path))
+            .map_err(|e| user_with_internal(
+                "We could not write a Rollbar event to the spool directory.",
+                "Make sure that the spool directory you have configured is writable and has enough free space.",
+                e
+            ))?;
+
+        Ok(path)
+    }
+
+    /// Lists the files containing spooled events.
+    pub (in crate) fn entries(&self) -> Result<Vec<PathBuf>, Error> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let entries = std::fs::read_dir(&self.dir).map_err(|e| user_with_internal(
+            "We could not read the directory used to spool Rollbar events.",
+            "Make sure that the spool directory you have configured is readable and try again.",
+            e
+        ))?;
+
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && is_spool_file(path))
+            .collect();
+
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Reads the spooled event stored in the provided file.
+    pub (in crate) fn read(&self, path: &Path) -> Result<Item, Error> {
+        let body = std::fs::read(path).map_err(|e| user_with_internal(
+            "We could not read a spooled Rollbar event.",
+            "Make sure that the spool directory you have configured is readable and try again.",
+            e
+        ))?;
+
+        let body = if is_compressed(path) {
+            let mut decompressed = Vec::new();
+            std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(body.as_slice()), &mut decompressed).map_err(|e| user_with_internal(
+                "We could not decompress a spooled Rollbar event.",
+                "The spooled event may be corrupt, you can remove it from the spool directory to stop this error from occurring.",
+                e
+            ))?;
+
+            decompressed
+        } else {
+            body
+        };
+
+        serde_json::from_slice(&body).map_err(|e| user_with_internal(
+            "We could not parse a spooled Rollbar event.",
+            "The spooled event may be corrupt, you can remove it from the spool directory to stop this error from occurring.",
+            e
+        ))
+    }
+
+    /// Determines whether the provided spooled event is older than this spool's
+    /// maximum age. The event's timestamp is used to determine its age, falling back
+    /// to the modification time of the file it was stored in.
+    pub (in crate) fn is_expired(&self, path: &Path, item: &Item) -> bool {
+        let max_age = match self.max_age {
+            Some(max_age) => max_age,
+            None => return false,
+        };
+
+        let created = match item.data.timestamp {
+            Some(timestamp) => std::time::UNIX_EPOCH + Duration::from_secs(timestamp),
+            None => match std::fs::metadata(path).and_then(|m| m.modified()) {
+                Ok(modified) => modified,
+                Err(_) => return false,
+            },
+        };
+
+        self.clock.system_time().duration_since(created).map(|age| age > max_age).unwrap_or_default()
+    }
+
+    /// Removes the spooled event stored in the provided file.
+    pub (in crate) fn remove(&self, path: &Path) -> Result<(), Error> {
+        std::fs::remove_file(path).map_err(|e| user_with_internal(
+            "We could not remove a spooled Rollbar event.",
+            "Make sure that the spool directory you have configured is writable and try again.",
+            e
+        ))
+    }
+}
+
+/// Determines whether the provided file contains a (compressed or uncompressed)
+/// spooled event.
+fn is_spool_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| !name.starts_with('.') && (name.ends_with(".json") || name.ends_with(".json.gz")))
+        .unwrap_or_default()
+}
+
+/// Determines whether the provided spool file contains a compressed event.
+fn is_compressed(path: &Path) -> bool {
+    path.extension().map(|ext| ext == "gz").unwrap_or_default()
+}
+
+/// Writes an item which could not be delivered to the spool, if one has been configured.
+pub (in crate) fn spool_item(spool: Option<&Spool>, item: &Item) {
+    if let Some(spool) = spool {
+        match spool.write(item) {
+            Ok(path) => debug!("Spooled undelivered Rollbar event to {}", path.display()),
+            Err(e) => error!("We could not spool an undelivered Rollbar event: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_spool_roundtrip() {
+        let spool = Spool::new(std::env::temp_dir().join(format!("rollbar-rs-spool-{}", helpers::new_uuid())));
+        assert!(spool.entries().unwrap().is_empty(), "a missing spool directory should have no entries");
+
+        let item: models::Item = (rollbar_format!(message = "Test message"), &Configuration::default()).into();
+        let path = spool.write(&item).unwrap();
+
+        assert_eq!(spool.entries().unwrap(), vec![path.clone()]);
+        assert_eq!(spool.read(&path).unwrap().data.uuid, item.data.uuid);
+
+        spool.remove(&path).unwrap();
+        assert!(spool.entries().unwrap().is_empty());
+
+        std::fs::remove_dir_all(spool.dir()).ok();
+    }
+
+    #[test]
+    fn test_compressed_spool() {
+        let spool = Spool::new(std::env::temp_dir().join(format!("rollbar-rs-spool-{}", helpers::new_uuid())));
+        let compressed = spool.clone().with_compression(true);
+
+        let first: models::Item = (rollbar_format!(message = "Uncompressed event"), &Configuration::default()).into();
+        let second: models::Item = (rollbar_format!(message = "Compressed event"), &Configuration::default()).into();
+        let first_path = spool.write(&first).unwrap();
+        let second_path = compressed.write(&second).unwrap();
+
+        assert!(second_path.to_string_lossy().ends_with(".json.gz"), "compressed events should be written to .json.gz files");
+        assert!(serde_json::from_slice::<serde_json::Value>(&std::fs::read(&second_path).unwrap()).is_err(), "the file should not contain plain JSON");
+
+        let mut entries = spool.entries().unwrap();
+        entries.sort();
+        let mut expected = vec![first_path, second_path.clone()];
+        expected.sort();
+        assert_eq!(entries, expected, "both compressed and uncompressed events should be listed");
+
+        for path in entries {
+            let item = spool.read(&path).unwrap();
+            let expected = if path == second_path { &second } else { &first };
+            assert_eq!(item.data.uuid, expected.data.uuid);
+        }
+
+        std::fs::remove_dir_all(spool.dir()).ok();
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let spool = Spool::new(std::env::temp_dir().join(format!("rollbar-rs-spool-{}", helpers::new_uuid())))
+            .with_max_age(Duration::from_secs(3600));
+
+        let fresh: models::Item = (rollbar_format!(message = "Fresh event"), &Configuration::default()).into();
+        let stale: models::Item = (rollbar_format!(message = "Stale event", timestamp = helpers::now_timestamp() - 7200), &Configuration::default()).into();
+
+        assert!(!spool.is_expired(Path::new("fresh.json"), &fresh));
+        assert!(spool.is_expired(Path::new("stale.json"), &stale));
+        assert!(!Spool::new(spool.dir()).is_expired(Path::new("stale.json"), &stale), "events should not expire without a max age");
+    }
+}
@@ -1,42 +1,717 @@
-#[cfg(feature = "async")]
-use std::sync::Arc;
-
 #[cfg(feature = "threaded")]
-use std::sync::{Arc, Condvar, mpsc::{sync_channel, SyncSender, Receiver}};
+use std::sync::{Condvar, mpsc::{sync_channel, SyncSender, Receiver}};
 
-use std::{time::Duration, sync::Mutex};
+use std::{collections::HashMap, net::{IpAddr, SocketAddr}, path::PathBuf, time::Duration, sync::{Arc, Mutex, RwLock, atomic::{AtomicBool, AtomicU64, Ordering}}};
 use serde::{Deserialize, Serialize};
+use crate::circuit_breaker::CircuitBreaker;
+use crate::clock::{Clock, SystemClock};
 use crate::models::Item;
+use crate::spool::{Spool, spool_item};
 use crate::{Configuration, Error};
 
-#[cfg(feature = "tokio")]
-use reqwest::Client;
+#[cfg(feature = "async")]
+use reqwest::Client as AsyncClient;
 
-#[cfg(not(feature = "async"))]
-use reqwest::blocking::Client;
+#[cfg(feature = "threaded")]
+use reqwest::blocking::Client as BlockingClient;
 
 use crate::errors::*;
 
-#[derive(Debug, Clone)]
+/// The IP address family which should be used to connect to Rollbar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpFamily {
+    /// Use whichever address family the system prefers.
+    #[default]
+    Any,
+
+    /// Only connect to Rollbar over IPv4.
+    V4,
+
+    /// Only connect to Rollbar over IPv6.
+    V6,
+}
+
+/// The format used to serialize events before they are sent to Rollbar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SerializationFormat {
+    /// Serializes events as JSON, which is the format accepted by the Rollbar API.
+    #[default]
+    Json,
+
+    /// Serializes events as MessagePack, which is more compact than JSON but is
+    /// only accepted by Rollbar-compatible ingesters which support it.
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+impl SerializationFormat {
+    /// Gets the `Content-Type` which is sent with requests in this format.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            SerializationFormat::Json => "application/json",
+            #[cfg(feature = "msgpack")]
+            SerializationFormat::MessagePack => "application/msgpack",
+        }
+    }
+}
+
+/// Determines what happens to events sent while the async transport is already
+/// sending its `max_concurrent` number of events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Events are queued until a slot becomes available, with events beyond the
+    /// queue's capacity being dropped.
+    Queue { capacity: usize },
+
+    /// Events are dropped immediately.
+    Drop,
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> Self {
+        OverflowPolicy::Queue { capacity: 100 }
+    }
+}
+
+/// A callback which is invoked whenever the transport fails to deliver an event
+/// to Rollbar.
+pub type ErrorHook = Arc<dyn Fn(&Error) + Send + Sync>;
+
+/// A callback which receives the serialized body of each request before it
+/// is sent to Rollbar.
+pub type SerializeHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// A callback which is invoked with whether an event was delivered to Rollbar once
+/// the transport has finished attempting to send it.
+pub type DeliveryCallback = Box<dyn FnOnce(bool) + Send>;
+
+/// An asynchronous callback which may modify each event before it is sent to
+/// Rollbar by the async transport.
+/// 
+/// # Example
+/// ```rust
+/// use std::{future::Future, pin::Pin, sync::Arc};
+/// use rollbar_rs::*;
+/// 
+/// let config = TransportConfig {
+///     async_before_send: Some(Arc::new(|mut data: types::Data| -> Pin<Box<dyn Future<Output = types::Data> + Send>> {
+///         Box::pin(async move {
+///             data.person = Some(Person::new("12345"));
+///             data
+///         })
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+#[cfg(feature = "async")]
+pub type AsyncBeforeSendHook = Arc<dyn Fn(crate::types::Data) -> std::pin::Pin<Box<dyn std::future::Future<Output = crate::types::Data> + Send>> + Send + Sync>;
+
+#[derive(Clone)]
 pub struct TransportConfig {
     pub endpoint: String,
+
+    /// The endpoint used to submit batches of items. When not set, this is
+    /// derived from the `endpoint` by replacing its trailing `item/` path.
+    pub item_batch_endpoint: Option<String>,
+
+    /// The endpoint used to record deploys. When not set, this is derived from
+    /// the `endpoint` by replacing its trailing `item/` path.
+    pub deploy_endpoint: Option<String>,
+
+    /// The endpoints to which items are sent for specific environments (like a
+    /// separate collector for `staging`), keyed by the name of the environment.
+    /// Items whose environment does not have an endpoint are sent to the `endpoint`.
+    pub endpoints_by_environment: HashMap<String, String>,
     pub timeout: Duration,
+
+    /// The maximum amount of time to wait while establishing a connection to
+    /// Rollbar. When not set, only the overall `timeout` applies.
+    pub connect_timeout: Option<Duration>,
     pub proxy: Option<String>,
+
+    /// The local address which connections to Rollbar should be made from. This
+    /// is useful on multi-homed hosts which need to send traffic from a specific
+    /// interface.
+    pub local_address: Option<IpAddr>,
+
+    /// The IP address family which should be used to connect to Rollbar. This is
+    /// ignored if a `local_address` has been set.
+    pub ip_family: IpFamily,
+
+    /// Overrides the addresses which specific hostnames resolve to, while still
+    /// using the hostname for the `Host` header and TLS verification. This allows
+    /// you to send events to a specific instance (for example, a local instance
+    /// which presents the production certificate). The port of each address is
+    /// ignored in favour of the port in the endpoint's URL.
+    pub resolve: Vec<(String, SocketAddr)>,
+
+    /// A PEM encoded root certificate which should be trusted when connecting
+    /// to Rollbar, in addition to the system's default roots. This is required
+    /// when connecting through a TLS-intercepting proxy, or to a self-hosted
+    /// Rollbar instance which uses a private certificate authority.
+    pub root_cert_pem: Option<Vec<u8>>,
+
+    /// Disables verification of Rollbar's TLS certificate. This is dangerous and
+    /// should only be used for testing, since it allows anyone to intercept the
+    /// events you send to Rollbar.
+    #[cfg(feature = "dangerous-tls")]
+    pub danger_accept_invalid_certs: bool,
+
+    /// The number of consecutive failures after which the transport will stop
+    /// sending events to Rollbar for the `circuit_breaker_cooldown` period. Events
+    /// reported while the circuit breaker is open are dropped. When not set, the
+    /// transport will always attempt to send events.
+    pub circuit_breaker_threshold: Option<u32>,
+
+    /// The amount of time to wait after the circuit breaker opens before probing
+    /// Rollbar to determine whether it has recovered.
+    pub circuit_breaker_cooldown: Duration,
+
+    /// The maximum number of events which the async transport will send to Rollbar
+    /// concurrently, preventing a flood of events from exhausting the runtime. When
+    /// not set, the number of concurrent sends is not limited.
+    pub max_concurrent: Option<usize>,
+
+    /// Determines what happens to events sent while the async transport is already
+    /// sending `max_concurrent` events. Dropped events are counted in the transport's
+    /// metrics, and spooled if a spool has been configured.
+    pub overflow_policy: OverflowPolicy,
+
+    /// The directory in which events which could not be delivered to Rollbar
+    /// (because it was unreachable, or the circuit breaker was open) are stored.
+    /// Spooled events may be re-sent using `Client::replay_spool`.
+    pub spool_dir: Option<PathBuf>,
+
+    /// The maximum age of spooled events which will be replayed, after which they
+    /// are considered stale and are discarded without being sent (default: 24 hours).
+    pub spool_max_age: Duration,
+
+    /// Whether events written to the spool are compressed using gzip, reducing the
+    /// disk space they use on devices with limited storage. Compressed and
+    /// uncompressed events are both replayed, so this may be changed at any time.
+    pub spool_compress: bool,
+
+    /// Whether the bodies of requests sent to Rollbar should be compressed using
+    /// gzip, which reduces the bandwidth used to send large traces.
+    pub compress_requests: bool,
+
+    /// The format used to serialize events sent to Rollbar. This defaults to JSON,
+    /// which is the only format accepted by the Rollbar API.
+    pub format: SerializationFormat,
+
+    /// The user agent which is sent with requests to Rollbar. When not set, this
+    /// defaults to `SierraSoftworks/rollbar-rs v{VERSION}`.
+    pub user_agent: Option<String>,
+
+    /// A callback which is invoked with the exact JSON body of each request
+    /// immediately before it is sent (it is not invoked for other formats). This is useful for verifying the wire
+    /// format in tests, or for auditing the data which leaves your application.
+    pub on_serialize: Option<SerializeHook>,
+
+    /// A callback which is invoked whenever an event could not be delivered to
+    /// Rollbar, including when Rollbar rejects the event (for example, due to an
+    /// invalid access token or payload).
+    pub on_error: Option<ErrorHook>,
+
+    /// An asynchronous callback which is awaited by the async transport before each
+    /// event is serialized, allowing it to be enriched with information which must
+    /// be fetched using IO (for example, details of the current user from a cache).
+    /// The event is not sent until the returned future completes, so slow hooks
+    /// delay the delivery of every event.
+    #[cfg(feature = "async")]
+    pub async_before_send: Option<AsyncBeforeSendHook>,
+
+    /// The clock used by the circuit breaker and to determine the age of spooled
+    /// events. This defaults to the [`SystemClock`], and may be replaced to test
+    /// these features deterministically.
+    pub clock: Arc<dyn Clock>,
+}
+
+impl std::fmt::Debug for TransportConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut f = f.debug_struct("TransportConfig");
+        f.field("endpoint", &self.endpoint)
+            .field("item_batch_endpoint", &self.item_batch_endpoint)
+            .field("deploy_endpoint", &self.deploy_endpoint)
+            .field("endpoints_by_environment", &self.endpoints_by_environment)
+            .field("timeout", &self.timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("proxy", &self.proxy)
+            .field("local_address", &self.local_address)
+            .field("ip_family", &self.ip_family)
+            .field("resolve", &self.resolve)
+            .field("root_cert_pem", &self.root_cert_pem.as_ref().map(|_| "<pem>"))
+            .field("circuit_breaker_threshold", &self.circuit_breaker_threshold)
+            .field("circuit_breaker_cooldown", &self.circuit_breaker_cooldown)
+            .field("max_concurrent", &self.max_concurrent)
+            .field("overflow_policy", &self.overflow_policy)
+            .field("spool_dir", &self.spool_dir)
+            .field("spool_max_age", &self.spool_max_age)
+            .field("spool_compress", &self.spool_compress)
+            .field("compress_requests", &self.compress_requests)
+            .field("format", &self.format)
+            .field("user_agent", &self.user_agent)
+            .field("on_serialize", &self.on_serialize.as_ref().map(|_| "<fn>"))
+            .field("on_error", &self.on_error.as_ref().map(|_| "<fn>"))
+            .field("clock", &self.clock);
+
+        #[cfg(feature = "async")]
+        f.field("async_before_send", &self.async_before_send.as_ref().map(|_| "<fn>"));
+
+        f.finish()
+    }
 }
 
 impl Default for TransportConfig {
     fn default() -> Self {
         TransportConfig {
             endpoint: "https://api.rollbar.com/api/1/item/".to_string(),
+            item_batch_endpoint: None,
+            deploy_endpoint: None,
+            endpoints_by_environment: HashMap::new(),
             timeout: Duration::from_millis(10000),
+            connect_timeout: None,
             proxy: None,
+            local_address: None,
+            ip_family: IpFamily::Any,
+            resolve: Vec::new(),
+            root_cert_pem: None,
+            #[cfg(feature = "dangerous-tls")]
+            danger_accept_invalid_certs: false,
+            circuit_breaker_threshold: None,
+            circuit_breaker_cooldown: Duration::from_secs(30),
+            max_concurrent: None,
+            overflow_policy: OverflowPolicy::default(),
+            spool_dir: None,
+            spool_max_age: Duration::from_secs(24 * 60 * 60),
+            spool_compress: false,
+            compress_requests: false,
+            format: SerializationFormat::Json,
+            user_agent: None,
+            on_serialize: None,
+            on_error: None,
+            #[cfg(feature = "async")]
+            async_before_send: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl TransportConfig {
+    /// Creates a new transport configuration which targets the Rollbar API
+    /// hosted at the provided base URL.
+    /// 
+    /// This is useful for self-hosted deployments which mount the Rollbar API
+    /// under a custom prefix, since the item and batch endpoints are derived
+    /// from the base URL automatically.
+    /// 
+    /// # Example
+    /// ```rust
+    /// use rollbar_rs::*;
+    /// 
+    /// let config = TransportConfig::from_base_url("https://rollbar.example.com/proxy");
+    /// assert_eq!(config.endpoint, "https://rollbar.example.com/proxy/api/1/item/");
+    /// ```
+    pub fn from_base_url(base_url: &str) -> Self {
+        TransportConfig {
+            endpoint: format!("{}/api/1/item/", base_url.trim_end_matches('/')),
+            ..Default::default()
+        }
+    }
+
+    /// Gets the endpoint to which items in the provided environment should be sent,
+    /// falling back to the default `endpoint` when the environment has no override.
+    pub fn endpoint_for(&self, environment: Option<&str>) -> &str {
+        environment
+            .and_then(|environment| self.endpoints_by_environment.get(environment))
+            .unwrap_or(&self.endpoint)
+    }
+
+    /// Gets the endpoint which should be used to submit batches of items.
+    pub fn batch_endpoint(&self) -> String {
+        self.item_batch_endpoint.clone().unwrap_or_else(|| self.sibling_endpoint("item_batch"))
+    }
+
+    /// Gets the endpoint which should be used to record deploys.
+    pub fn deploy_endpoint(&self) -> String {
+        self.deploy_endpoint.clone().unwrap_or_else(|| self.sibling_endpoint("deploy"))
+    }
+
+    /// Derives the URL of another API endpoint from the item `endpoint`.
+    fn sibling_endpoint(&self, name: &str) -> String {
+        let base = self.endpoint.trim_end_matches('/');
+        match base.strip_suffix("/item") {
+            Some(prefix) => format!("{}/{}/", prefix, name),
+            None => format!("{}/{}/", base, name),
+        }
+    }
+}
+
+/// Builds a reqwest client (either async or blocking) using the provided
+/// transport configuration, returning early with an error if it is invalid.
+macro_rules! build_client {
+    ($builder:expr, $config:ident) => {
+        {
+            let mut client = $builder
+                .gzip(true)
+                .timeout($config.timeout)
+                .user_agent($config.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT));
+
+            if let Some(connect_timeout) = $config.connect_timeout {
+                client = client.connect_timeout(connect_timeout);
+            }
+
+            if let Some(proxy) = &$config.proxy {
+                client = client.proxy(reqwest::Proxy::all(proxy).map_err(|e| user_with_internal(
+                    "We could not configure Rollbar to use the proxy you provided.",
+                    "Make sure that you have specified a valid proxy URL in your configuration and try again.",
+                    e
+                ))?);
+            }
+
+            // Binding to the unspecified address of a family forces connections to use that family.
+            let local_address = $config.local_address.or(match $config.ip_family {
+                IpFamily::Any => None,
+                IpFamily::V4 => Some(IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED)),
+                IpFamily::V6 => Some(IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED)),
+            });
+
+            if let Some(local_address) = local_address {
+                client = client.local_address(local_address);
+            }
+
+            for (host, addr) in &$config.resolve {
+                client = client.resolve(host, *addr);
+            }
+
+            if let Some(root_cert_pem) = &$config.root_cert_pem {
+                client = client.add_root_certificate(reqwest::Certificate::from_pem(root_cert_pem).map_err(|e| user_with_internal(
+                    "We could not load the root certificate you provided for Rollbar.",
+                    "Make sure that you have provided a valid PEM encoded certificate in your configuration and try again.",
+                    e
+                ))?);
+            }
+
+            #[cfg(feature = "dangerous-tls")]
+            if $config.danger_accept_invalid_certs {
+                warn!("TLS certificate verification has been disabled for Rollbar, this should never be used in production.");
+                client = client.danger_accept_invalid_certs(true);
+            }
+
+            client.build().map_err(|e| user_with_internal(
+                "We could not configure Rollbar based on the configuration you have provided.",
+                "Make sure that you have specified a valid configuration and try again.",
+                e
+            ))?
+        }
+    };
+}
+
+const DEFAULT_USER_AGENT: &str = concat!("SierraSoftworks/rollbar-rs v", env!("CARGO_PKG_VERSION"));
+
+pub(crate) static MISSING_TOKEN_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Logs a warning the first time that an event is reported without an access token.
+///
+/// This is called by the clients when they drop a tokenless event (before it ever
+/// reaches a transport), as well as by the transports themselves.
+pub(crate) fn warn_no_access_token() {
+    warn_missing_token(&MISSING_TOKEN_WARNED);
+}
+
+/// Logs a warning the first time that an event is sent without an access token,
+/// returning `true` if this call emitted the warning.
+fn warn_missing_token(warned: &AtomicBool) -> bool {
+    if warned.swap(true, Ordering::Relaxed) {
+        debug!("Skipping sending payload to Rollbar since there is no access token");
+        false
+    } else {
+        warn!("Events are not being sent to Rollbar because no access token has been configured. Use rollbar_rs::set_token() to configure one.");
+        true
+    }
+}
+
+/// Serializes the provided item into the body which is sent to Rollbar, passing
+/// JSON bodies to the `on_serialize` hook if one has been configured.
+///
+/// If the item cannot be serialized, its `custom` field (which is the most likely
+/// culprit) is replaced with a description of the error and serialization is
+/// retried, ensuring that a degraded report is still sent. If that also fails,
+/// the error is passed to the `on_error` hook and the item is dropped.
+fn serialize_item(item: &Item, format: SerializationFormat, on_serialize: Option<&SerializeHook>, on_error: Option<&ErrorHook>) -> Option<Vec<u8>> {
+    serialize_item_with(item, format, on_serialize, on_error, encode_item)
+}
+
+fn encode_item(item: &Item, format: SerializationFormat) -> Result<Vec<u8>, String> {
+    match format {
+        SerializationFormat::Json => serde_json::to_vec(item).map_err(|e| e.to_string()),
+        #[cfg(feature = "msgpack")]
+        SerializationFormat::MessagePack => rmp_serde::to_vec_named(item).map_err(|e| e.to_string()),
+    }
+}
+
+fn serialize_item_with<E>(item: &Item, format: SerializationFormat, on_serialize: Option<&SerializeHook>, on_error: Option<&ErrorHook>, encode: E) -> Option<Vec<u8>>
+    where E: Fn(&Item, SerializationFormat) -> Result<Vec<u8>, String>
+{
+    let body = encode(item, format).or_else(|e| {
+        if item.data.custom.is_none() {
+            return Err(e);
+        }
+
+        warn!("We could not serialize the payload to send to Rollbar, retrying without its custom data: {}", e);
+        let mut degraded = item.clone();
+        degraded.data.custom = Some(crate::map!{ serialization_error: e });
+        encode(&degraded, format)
+    });
+
+    match body {
+        Ok(body) => {
+            if let (SerializationFormat::Json, Some(on_serialize)) = (format, on_serialize) {
+                on_serialize(&String::from_utf8_lossy(&body));
+            }
+
+            Some(body)
+        },
+        Err(e) => {
+            let err = system(
+                &format!("We could not serialize the payload to send to Rollbar: {}", e),
+                "Please report this issue to us on GitHub.",
+            );
+
+            error!("{}", err);
+            if let Some(on_error) = on_error {
+                on_error(&err);
+            }
+
+            None
+        }
+    }
+}
+
+/// Compresses the provided request body using gzip if compression is enabled,
+/// returning the body along with whether it was compressed. If the body cannot be
+/// compressed, it is sent uncompressed.
+fn compress_body(body: Vec<u8>, compress: bool) -> (Vec<u8>, bool) {
+    if !compress {
+        return (body, false);
+    }
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    match std::io::Write::write_all(&mut encoder, &body).and_then(|_| encoder.finish()) {
+        Ok(compressed) => (compressed, true),
+        Err(e) => {
+            warn!("We could not compress the payload to send to Rollbar, it will be sent uncompressed: {}", e);
+            (body, false)
+        }
+    }
+}
+
+/// Checks the response received from Rollbar, returning an error describing why
+/// the event was rejected if the request was not successful.
+fn check_response(status: reqwest::StatusCode, body: &[u8]) -> Result<Option<RollbarResponse>, Error> {
+    let response: Option<RollbarResponse> = serde_json::from_slice(body).ok();
+
+    match &response {
+        Some(r) if r.err != 0 || !status.is_success() => Err(user(
+            &format!("Rollbar rejected the event we sent to it ({}): {}", status, r.message.as_deref().unwrap_or("no reason was provided")),
+            "Make sure that your access token is valid and that the event you are reporting is valid, then try again.",
+        )),
+        None if !status.is_success() => Err(user(
+            &format!("Rollbar rejected the event we sent to it ({}).", status),
+            "Make sure that your access token is valid and that the event you are reporting is valid, then try again.",
+        )),
+        _ => Ok(response),
+    }
+}
+
+/// The category of an error which occurred while sending an event to Rollbar,
+/// which determines how it is logged and whether the event may be retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SendFailure {
+    /// The request timed out, and may succeed if it is retried.
+    Timeout,
+    /// We could not connect to Rollbar, and the request may succeed if it is retried.
+    Connect,
+    /// Rollbar's response could not be decoded. The event was delivered if the
+    /// response's status indicated success.
+    Decode,
+    /// Any other failure (like an invalid request), which will not succeed if it
+    /// is retried.
+    Other,
+}
+
+impl SendFailure {
+    fn classify(err: &reqwest::Error) -> Self {
+        if err.is_timeout() {
+            SendFailure::Timeout
+        } else if err.is_connect() {
+            SendFailure::Connect
+        } else if err.is_decode() {
+            SendFailure::Decode
+        } else {
+            SendFailure::Other
+        }
+    }
+}
+
+/// Converts an error encountered while sending an event into the result of the
+/// send, along with whether it may be retried. The `status` is provided when the
+/// error occurred while reading Rollbar's response.
+fn send_failed(err: reqwest::Error, status: Option<reqwest::StatusCode>) -> (Result<Option<RollbarResponse>, Error>, bool) {
+    match SendFailure::classify(&err) {
+        SendFailure::Timeout => (Err(system_with_internal(
+            "We timed out while sending the payload to Rollbar.",
+            "Make sure that Rollbar is reachable, or increase the timeout used by your transport.",
+            err
+        )), true),
+        SendFailure::Connect => (Err(system_with_internal(
+            "We could not connect to Rollbar to send the payload.",
+            "Make sure that your network connection is working and that Rollbar is reachable.",
+            err
+        )), true),
+        SendFailure::Decode => match status {
+            Some(status) if status.is_success() => {
+                debug!("We could not decode Rollbar's response, but it accepted the payload ({}): {}", status, err);
+                (Ok(None), false)
+            },
+            Some(status) => (check_response(status, &[]), is_retryable(status)),
+            None => (Err(system_with_internal(
+                "We could not decode the response we received from Rollbar.",
+                "Make sure that your endpoint points to a Rollbar compatible API and try again.",
+                err
+            )), false),
+        },
+        SendFailure::Other => (Err(system_with_internal(
+            "We could not send the payload to Rollbar.",
+            "Make sure that your transport is configured correctly and try again.",
+            err
+        )), false),
+    }
+}
+
+/// Invokes the delivery callback for an event, if one was provided.
+fn complete(on_complete: Option<DeliveryCallback>, delivered: bool) {
+    if let Some(on_complete) = on_complete {
+        on_complete(delivered);
+    }
+}
+
+/// Logs an error which occurred while sending an event to Rollbar and passes it
+/// to the `on_error` hook, if one has been configured.
+fn handle_error(err: Error, retryable: bool, on_error: Option<&ErrorHook>) {
+    if retryable {
+        warn!("We could not send the payload to Rollbar: {}", err);
+    } else {
+        error!("We could not send the payload to Rollbar: {}", err);
+    }
+
+    if let Some(on_error) = on_error {
+        on_error(&err);
+    }
+}
+
+/// A snapshot of the metrics gathered by a transport.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TransportMetrics {
+    /// The number of events which were dropped without being sent to Rollbar
+    /// (for example, because the circuit breaker was open).
+    pub dropped: u64,
+
+    /// The time taken by the most recent request to Rollbar.
+    pub last_send_duration: Option<Duration>,
+
+    /// A moving average of the time taken by requests to Rollbar, which weights
+    /// recent requests more heavily.
+    pub average_send_duration: Option<Duration>,
+
+    /// The number of events which were not sent because they were below the
+    /// configured log level. This is only tracked by [`crate::Client::metrics`].
+    pub suppressed: u64,
+
+    /// The number of events which were not sent because they exceeded the configured
+    /// `max_events_per_window`. This is only tracked by [`crate::Client::metrics`]
+    /// and [`crate::metrics`].
+    pub rate_limited: u64,
+}
+
+/// The metrics gathered by a transport, shared between it and its workers.
+#[derive(Debug, Default)]
+struct Metrics {
+    dropped: AtomicU64,
+    last_send_micros: AtomicU64,
+    average_send_micros: AtomicU64,
+}
+
+impl Metrics {
+    fn snapshot(&self) -> TransportMetrics {
+        let duration = |micros: u64| if micros == 0 { None } else { Some(Duration::from_micros(micros)) };
+
+        TransportMetrics {
+            dropped: self.dropped.load(Ordering::Relaxed),
+            last_send_duration: duration(self.last_send_micros.load(Ordering::Relaxed)),
+            average_send_duration: duration(self.average_send_micros.load(Ordering::Relaxed)),
+            suppressed: 0,
+            rate_limited: 0,
         }
     }
+
+    /// Records the time taken to send a request to Rollbar.
+    fn record_send(&self, duration: Duration) {
+        let micros = (duration.as_micros() as u64).max(1);
+        self.last_send_micros.store(micros, Ordering::Relaxed);
+
+        // An exponentially weighted moving average, with each request contributing a fifth.
+        self.average_send_micros.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |average| {
+            if average == 0 {
+                Some(micros)
+            } else {
+                Some(((average as i128) + (micros as i128 - average as i128) / 5) as u64)
+            }
+        }).ok();
+    }
 }
 
 pub trait Transport: Send + Sync + Sized {
     fn new(config: &TransportConfig) -> Result<Self, Error>;
     fn send(&self, event: TransportEvent);
+
+    /// Sends an event, invoking `on_complete` with whether it was delivered to Rollbar
+    /// once the transport has finished attempting to send it.
+    /// 
+    /// If the callback is dropped without being invoked, the event should be treated
+    /// as undelivered. Transports which cannot confirm delivery report every event as
+    /// undelivered, which is the default behaviour.
+    fn send_with_callback(&self, event: TransportEvent, on_complete: DeliveryCallback) {
+        self.send(event);
+        on_complete(false);
+    }
+
+    /// Gets a snapshot of the metrics gathered by this transport.
+    fn metrics(&self) -> TransportMetrics {
+        TransportMetrics::default()
+    }
+
+    /// Gets the spool in which this transport stores events it could not deliver.
+    fn spool(&self) -> Option<&Spool> {
+        None
+    }
+
+    /// Rebuilds the HTTP client used by this transport from the configuration it
+    /// was created with.
+    /// 
+    /// Long-lived processes may use this to recover if the underlying client enters
+    /// a bad state (for example, following a change in network configuration),
+    /// without needing to recreate the transport. Events which are already being
+    /// sent continue to use the previous client.
+    fn reset(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Determines whether a request which received the provided status should be retried.
+fn is_retryable(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
 }
 
 pub struct TransportEvent<'a> {
@@ -44,102 +719,365 @@ pub struct TransportEvent<'a> {
     pub payload: Item,
 }
 
+/// Limits the number of events which the async transport sends concurrently.
+#[cfg(feature = "async")]
+#[derive(Debug)]
+struct ConcurrencyLimiter {
+    semaphore: Arc<tokio::sync::Semaphore>,
+    queued: std::sync::atomic::AtomicUsize,
+    policy: OverflowPolicy,
+}
+
+/// The outcome of attempting to admit an event to the async transport.
+#[cfg(feature = "async")]
+enum Admission {
+    Ready(tokio::sync::OwnedSemaphorePermit),
+    Queued,
+    Rejected,
+}
+
+#[cfg(feature = "async")]
+impl ConcurrencyLimiter {
+    fn new(max_concurrent: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            semaphore: Arc::new(tokio::sync::Semaphore::new(max_concurrent)),
+            queued: std::sync::atomic::AtomicUsize::new(0),
+            policy,
+        }
+    }
+
+    fn admit(&self) -> Admission {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Admission::Ready(permit);
+        }
+
+        match self.policy {
+            OverflowPolicy::Drop => Admission::Rejected,
+            OverflowPolicy::Queue { capacity } => {
+                match self.queued.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| if queued < capacity { Some(queued + 1) } else { None }) {
+                    Ok(_) => Admission::Queued,
+                    Err(_) => Admission::Rejected,
+                }
+            }
+        }
+    }
+
+    async fn acquire(&self, admission: Admission) -> Option<tokio::sync::OwnedSemaphorePermit> {
+        match admission {
+            Admission::Ready(permit) => Some(permit),
+            Admission::Queued => {
+                let permit = self.semaphore.clone().acquire_owned().await.ok();
+                self.queued.fetch_sub(1, Ordering::SeqCst);
+                permit
+            },
+            Admission::Rejected => None,
+        }
+    }
+}
+
+/// Tracks the number of events which the async transport is currently sending,
+/// allowing callers to wait for all of them to complete.
+#[cfg(feature = "async")]
+#[derive(Debug, Default)]
+struct InFlight {
+    count: std::sync::atomic::AtomicUsize,
+    idle: tokio::sync::Notify,
+}
+
+/// Marks an event as being sent until it is dropped.
+#[cfg(feature = "async")]
+struct InFlightGuard(Arc<InFlight>);
+
+#[cfg(feature = "async")]
+impl InFlight {
+    fn start(self: &Arc<Self>) -> InFlightGuard {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(self.clone())
+    }
+
+    async fn wait_idle(&self) {
+        loop {
+            // The notification future is created before checking the count so that
+            // a send completing in between is not missed.
+            let idle = self.idle.notified();
+            if self.count.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+
+            idle.await;
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
 #[cfg(feature = "async")]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct TokioTransport {
-    endpoint: Arc<String>,
-    client: Arc<Client>,
+    config: Arc<TransportConfig>,
+    client: Arc<RwLock<Arc<AsyncClient>>>,
+    compress_requests: bool,
+    format: SerializationFormat,
+    on_serialize: Option<SerializeHook>,
+    on_error: Option<ErrorHook>,
+    before_send: Option<AsyncBeforeSendHook>,
+    breaker: Arc<CircuitBreaker>,
+    metrics: Arc<Metrics>,
+    spool: Option<Arc<Spool>>,
+    limiter: Option<Arc<ConcurrencyLimiter>>,
+    in_flight: Arc<InFlight>,
+}
+
+#[cfg(feature = "async")]
+impl std::fmt::Debug for TokioTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokioTransport")
+            .field("config", &self.config)
+            .field("client", &self.client)
+            .field("breaker", &self.breaker)
+            .field("metrics", &self.metrics)
+            .field("limiter", &self.limiter)
+            .field("in_flight", &self.in_flight)
+            .finish()
+    }
 }
 
 #[cfg(feature = "async")]
 impl Transport for TokioTransport {
     fn new(config: &TransportConfig) -> Result<Self, Error> {
-        let mut client = Client::builder()
-            .gzip(true)
-            .timeout(config.timeout)
-            .user_agent(concat!("SierraSoftworks/rollbar-rs v", env!("CARGO_PKG_VERSION")));
-        
-        if let Some(proxy) = &config.proxy {
-            client = client.proxy(reqwest::Proxy::all(proxy).map_err(|e| user_with_internal(
-                "We could not configure Rollbar to use the proxy you provided.",
-                "Make sure that you have specified a valid proxy URL in your configuration and try again.",
-                e
-            ))?);
-        }
-
-        let client = client.build().map_err(|e| user_with_internal(
-            "We could not configure Rollbar based on the configuration you have provided.",
-            "Make sure that you have specified a valid configuration and try again.",
-            e
-        ))?;
+        let client = build_client!(AsyncClient::builder(), config);
 
         Ok(Self {
-            endpoint: Arc::new(config.endpoint.clone()),
-            client: Arc::new(client),
+            config: Arc::new(config.clone()),
+            client: Arc::new(RwLock::new(Arc::new(client))),
+            compress_requests: config.compress_requests,
+            format: config.format,
+            on_serialize: config.on_serialize.clone(),
+            on_error: config.on_error.clone(),
+            before_send: config.async_before_send.clone(),
+            breaker: Arc::new(CircuitBreaker::new(config.circuit_breaker_threshold, config.circuit_breaker_cooldown, config.clock.clone())),
+            metrics: Arc::new(Metrics::default()),
+            spool: config.spool_dir.clone().map(|dir| Arc::new(Spool::new(dir).with_max_age(config.spool_max_age).with_compression(config.spool_compress).with_clock(config.clock.clone()))),
+            limiter: config.max_concurrent.map(|max_concurrent| Arc::new(ConcurrencyLimiter::new(max_concurrent, config.overflow_policy))),
+            in_flight: Arc::new(InFlight::default()),
         })
     }
 
     fn send(&self, event: TransportEvent) {
-        let client = self.client.clone();
-        let endpoint = self.endpoint.clone();
+        self.dispatch(event, None);
+    }
+
+    fn send_with_callback(&self, event: TransportEvent, on_complete: DeliveryCallback) {
+        self.dispatch(event, Some(on_complete));
+    }
+
+    fn metrics(&self) -> TransportMetrics {
+        self.metrics.snapshot()
+    }
+
+    fn spool(&self) -> Option<&Spool> {
+        self.spool.as_deref()
+    }
+
+    fn reset(&self) -> Result<(), Error> {
+        let config = &self.config;
+        let client = build_client!(AsyncClient::builder(), config);
+        *self.client.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(client);
+
+        debug!("TokioTransport: Rebuilt the HTTP client");
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl TokioTransport {
+    /// Sends an event in the background, invoking `on_complete` (if provided) with
+    /// whether it was delivered once sending has finished.
+    fn dispatch(&self, event: TransportEvent, on_complete: Option<DeliveryCallback>) {
+        let client = self.client.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let endpoint = self.config.endpoint_for(event.payload.data.environment.as_deref()).to_string();
+        let on_error = self.on_error.clone();
+        let spool = self.spool.clone();
+        let metrics = self.metrics.clone();
         let access_token = event.config.access_token.clone();
 
         match access_token {
             Some(access_token) => {
+                let permit = match self.breaker.allow() {
+                    Some(permit) => permit,
+                    None => {
+                        debug!("TokioTransport: Dropping payload since the circuit breaker is open");
+                        self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                        spool_item(self.spool.as_deref(), &event.payload);
+                        return complete(on_complete, false);
+                    },
+                };
+
+                let admission = match &self.limiter {
+                    Some(limiter) => match limiter.admit() {
+                        Admission::Rejected => {
+                            debug!("TokioTransport: Dropping payload since too many payloads are already being sent");
+                            self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                            spool_item(self.spool.as_deref(), &event.payload);
+                            return complete(on_complete, false);
+                        },
+                        admission => Some((limiter.clone(), admission)),
+                    },
+                    None => None,
+                };
+
+                let format = self.format;
+                let compress_requests = self.compress_requests;
+                let on_serialize = self.on_serialize.clone();
+                let before_send = self.before_send.clone();
+                let mut item = event.payload;
+                let in_flight = self.in_flight.start();
                 tokio::spawn(async move {
+                    let _in_flight = in_flight;
+                    let _permit = match admission {
+                        Some((limiter, admission)) => limiter.acquire(admission).await,
+                        None => None,
+                    };
+
+                    if let Some(before_send) = before_send {
+                        item.data = before_send(std::mem::take(&mut item.data)).await;
+                    }
+
+                    let body = match serialize_item(&item, format, on_serialize.as_ref(), on_error.as_ref()) {
+                        Some(body) => body,
+                        None => return complete(on_complete, false),
+                    };
+                    let (body, compressed) = compress_body(body, compress_requests);
+
                     let mut req = client
                         .post(endpoint.as_str())
-                        .json(&event.payload);
+                        .header(reqwest::header::CONTENT_TYPE, format.content_type())
+                        .body(body);
+
+                    if compressed {
+                        req = req.header(reqwest::header::CONTENT_ENCODING, "gzip");
+                    }
         
                     if let Some(mut access_token) = reqwest::header::HeaderValue::from_str(&access_token).ok() {
                         access_token.set_sensitive(true);
                         req = req.header("X-Rollbar-Access-Token", access_token);
                     }
         
-                    match req.send().await {
-                        Ok(resp) => debug!("Successfully sent payload to Rollbar: {}", resp.json().await.ok().and_then(|r: RollbarResponse| serde_json::to_string_pretty(&r).ok()).unwrap_or_default()),
-                        Err(e) => error!("We could not send the payload to Rollbar: {}", e),
+                    let started = std::time::Instant::now();
+                    let (result, retryable) = match req.send().await {
+                        Ok(resp) => {
+                            let status = resp.status();
+                            match resp.bytes().await {
+                                Ok(body) => (check_response(status, &body), is_retryable(status)),
+                                Err(e) => send_failed(e, Some(status)),
+                            }
+                        },
+                        Err(e) => send_failed(e, None),
+                    };
+                    metrics.record_send(started.elapsed());
+
+                    let delivered = match result {
+                        Ok(resp) => {
+                            permit.record_success();
+                            debug!("Successfully sent payload to Rollbar: {}", resp.and_then(|r| serde_json::to_string_pretty(&r).ok()).unwrap_or_default());
+                            true
+                        },
+                        Err(e) => {
+                            if retryable {
+                                permit.record_failure();
+                                spool_item(spool.as_deref(), &item);
+                            } else {
+                                // Rollbar rejected the event, but it is reachable, so this doesn't count
+                                // towards opening the circuit breaker.
+                                permit.record_success();
+                            }
+
+                            handle_error(e, retryable, on_error.as_ref());
+                            false
+                        },
                     };
+
+                    complete(on_complete, delivered);
                 });
             },
-            None => {}
+            None => {
+                warn_no_access_token();
+                complete(on_complete, false);
+            }
         }        
     }
+
+    /// Records a deploy using Rollbar's deploy API, returning its ID.
+    pub(crate) async fn send_deploy(&self, access_token: &str, deploy: &DeployRequest<'_>) -> Result<u64, Error> {
+        let client = self.client.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let mut req = client.post(self.config.deploy_endpoint()).json(deploy);
+
+        if let Some(mut access_token) = reqwest::header::HeaderValue::from_str(access_token).ok() {
+            access_token.set_sensitive(true);
+            req = req.header("X-Rollbar-Access-Token", access_token);
+        }
+
+        let resp = req.send().await.map_err(deploy_failed)?;
+        let status = resp.status();
+        let body = resp.bytes().await.map_err(deploy_failed)?;
+        check_deploy_response(status, &body)
+    }
+
+    /// Waits for all of the events which are currently being sent by this transport
+    /// to complete, or for the timeout to elapse.
+    /// 
+    /// This should be called before your application exits (or, in serverless
+    /// environments, before the invocation completes) to ensure that events which
+    /// are still being sent in the background are not lost.
+    pub async fn flush(&self, timeout: Duration) -> Result<(), Error> {
+        tokio::time::timeout(timeout, self.in_flight.wait_idle()).await.map_err(|_| user(
+            &format!("Timed out after {:?} while waiting for events to be sent to Rollbar.", timeout),
+            "Make sure that your application can reach the Rollbar API, or increase the flush timeout."))
+    }
+}
+
+/// An item which is waiting to be sent by the threaded transport's worker.
+#[cfg(feature = "threaded")]
+struct QueuedItem {
+    access_token: String,
+    item: Item,
+    on_complete: Option<DeliveryCallback>,
 }
 
 #[cfg(feature = "threaded")]
 #[derive(Debug)]
 pub struct ThreadedTransport {
-    chan: SyncSender<Option<(String, Item)>>,
+    config: TransportConfig,
+    client: Arc<RwLock<BlockingClient>>,
+    chan: SyncSender<Option<QueuedItem>>,
     running: Arc<Mutex<bool>>,
     running_changed: Arc<Condvar>,
+    metrics: Arc<Metrics>,
+    spool: Option<Spool>,
     _thread: std::thread::JoinHandle<()>,
 }
 
 #[cfg(feature = "threaded")]
 impl Transport for ThreadedTransport {
     fn new(config: &TransportConfig) -> Result<Self, Error> {
-        let mut client = Client::builder()
-            .gzip(true)
-            .timeout(config.timeout)
-            .user_agent(concat!("SierraSoftworks/rollbar-rs v", env!("CARGO_PKG_VERSION")));
-        
-        if let Some(proxy) = &config.proxy {
-            client = client.proxy(reqwest::Proxy::all(proxy).map_err(|e| user_with_internal(
-                "We could not configure Rollbar to use the proxy you provided.",
-                "Make sure that you have specified a valid proxy URL in your configuration and try again.",
-                e
-            ))?);
-        }
-
-        let client = client.build().map_err(|e| user_with_internal(
-            "We could not configure Rollbar based on the configuration you have provided.",
-            "Make sure that you have specified a valid configuration and try again.",
-            e
-        ))?;
-        let endpoint = config.endpoint.clone();
+        let client = Arc::new(RwLock::new(build_client!(BlockingClient::builder(), config)));
+        let routing = config.clone();
+        let compress_requests = config.compress_requests;
+        let format = config.format;
+        let on_serialize = config.on_serialize.clone();
+        let on_error = config.on_error.clone();
+        let breaker = Arc::new(CircuitBreaker::new(config.circuit_breaker_threshold, config.circuit_breaker_cooldown, config.clock.clone()));
+        let metrics = Arc::new(Metrics::default());
+        let spool = config.spool_dir.clone().map(|dir| Spool::new(dir).with_max_age(config.spool_max_age).with_compression(config.spool_compress).with_clock(config.clock.clone()));
         
-        let (tx, rx): (SyncSender<Option<(String, Item)>>, Receiver<Option<(String, Item)>>) = sync_channel(100);
+        let (tx, rx): (SyncSender<Option<QueuedItem>>, Receiver<Option<QueuedItem>>) = sync_channel(100);
         let running = Arc::new(Mutex::new(true));
         let running_changed = Arc::new(Condvar::new());
 
@@ -148,13 +1086,38 @@ impl Transport for ThreadedTransport {
         let thread = {
             let running = running.clone();
             let running_changed = running_changed.clone();
+            let metrics = metrics.clone();
+            let spool = spool.clone();
+            let client = client.clone();
 
             std::thread::spawn(move || {
-                while let Some((access_token, item)) = rx.recv().unwrap_or(None) {
+                let send_item = |access_token: String, item: Item| -> bool {
                     debug!("ThreadedTransport: Received item to send to Rollbar");
+                    let permit = match breaker.allow() {
+                        Some(permit) => permit,
+                        None => {
+                            debug!("ThreadedTransport: Dropping payload since the circuit breaker is open");
+                            metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                            spool_item(spool.as_ref(), &item);
+                            return false;
+                        },
+                    };
+
+                    let body = match serialize_item(&item, format, on_serialize.as_ref(), on_error.as_ref()) {
+                        Some(body) => body,
+                        None => return false,
+                    };
+                    let (body, compressed) = compress_body(body, compress_requests);
+
+                    let client = client.read().unwrap_or_else(|e| e.into_inner()).clone();
                     let mut req = client
-                        .post(endpoint.as_str())
-                        .json(&item);
+                        .post(routing.endpoint_for(item.data.environment.as_deref()))
+                        .header(reqwest::header::CONTENT_TYPE, format.content_type())
+                        .body(body);
+
+                    if compressed {
+                        req = req.header(reqwest::header::CONTENT_ENCODING, "gzip");
+                    }
             
                     if let Some(mut access_token) = reqwest::header::HeaderValue::from_str(access_token.as_str()).ok() {
                         access_token.set_sensitive(true);
@@ -162,10 +1125,58 @@ impl Transport for ThreadedTransport {
                     }
             
                     debug!("ThreadedTransport: Sending item to Rollbar");
-                    match req.send() {
-                        Ok(resp) => debug!("Successfully sent payload to Rollbar: {}", resp.json().ok().and_then(|r: RollbarResponse| serde_json::to_string_pretty(&r).ok()).unwrap_or_default()),
-                        Err(e) => error!("We could not send the payload to Rollbar: {}", e),
+                    let started = std::time::Instant::now();
+                    let (result, retryable) = match req.send() {
+                        Ok(resp) => {
+                            let status = resp.status();
+                            match resp.bytes() {
+                                Ok(body) => (check_response(status, &body), is_retryable(status)),
+                                Err(e) => send_failed(e, Some(status)),
+                            }
+                        },
+                        Err(e) => send_failed(e, None),
                     };
+                    metrics.record_send(started.elapsed());
+
+                    match result {
+                        Ok(resp) => {
+                            permit.record_success();
+                            debug!("Successfully sent payload to Rollbar: {}", resp.and_then(|r| serde_json::to_string_pretty(&r).ok()).unwrap_or_default());
+                            true
+                        },
+                        Err(e) => {
+                            if retryable {
+                                permit.record_failure();
+                                spool_item(spool.as_ref(), &item);
+                            } else {
+                                // Rollbar rejected the event, but it is reachable, so this doesn't count
+                                // towards opening the circuit breaker.
+                                permit.record_success();
+                            }
+
+                            handle_error(e, retryable, on_error.as_ref());
+                            false
+                        },
+                    }
+                };
+
+                while let Some(QueuedItem { access_token, item, on_complete }) = rx.recv().unwrap_or(None) {
+                    // A panic while sending an item must not stop the worker, otherwise every
+                    // subsequent event would be silently queued into a channel which is never read.
+                    let delivered = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| send_item(access_token, item))) {
+                        Ok(delivered) => delivered,
+                        Err(payload) => {
+                            let err = system(
+                                &format!("The Rollbar transport's worker panicked while sending a payload: {}", crate::helpers::panic_message(payload.as_ref())),
+                                "Please report this issue to us on GitHub.",
+                            );
+
+                            handle_error(err, false, on_error.as_ref());
+                            false
+                        },
+                    };
+
+                    complete(on_complete, delivered);
                 }
 
                 let mut is_running = running.lock().unwrap();
@@ -177,22 +1188,73 @@ impl Transport for ThreadedTransport {
         };
 
         Ok(Self {
+            config: config.clone(),
+            client,
             chan: tx,
             running,
             running_changed,
+            metrics,
+            spool,
             _thread: thread,
         })
     }
 
     fn send(&self, event: TransportEvent) {
+        self.enqueue(event, None);
+    }
+
+    fn send_with_callback(&self, event: TransportEvent, on_complete: DeliveryCallback) {
+        self.enqueue(event, Some(on_complete));
+    }
+
+    fn metrics(&self) -> TransportMetrics {
+        self.metrics.snapshot()
+    }
+
+    fn spool(&self) -> Option<&Spool> {
+        self.spool.as_ref()
+    }
+
+    fn reset(&self) -> Result<(), Error> {
+        let config = &self.config;
+        let client = build_client!(BlockingClient::builder(), config);
+        *self.client.write().unwrap_or_else(|e| e.into_inner()) = client;
+
+        debug!("ThreadedTransport: Rebuilt the HTTP client");
+        Ok(())
+    }
+}
+
+#[cfg(feature = "threaded")]
+impl ThreadedTransport {
+    /// Queues an event to be sent by the worker thread, which invokes `on_complete`
+    /// (if provided) with whether it was delivered once sending has finished.
+    fn enqueue(&self, event: TransportEvent, on_complete: Option<DeliveryCallback>) {
         if let Some(access_token) = event.config.access_token.clone() {
-            self.chan.send(Some((access_token, event.payload))).unwrap_or_else(|e| {
+            self.chan.send(Some(QueuedItem { access_token, item: event.payload, on_complete })).unwrap_or_else(|e| {
                 error!("We could not send the payload to Rollbar: {}", e);
             });
         } else {
-            debug!("Skipping sending payload to Rollbar since there is no access token");
+            warn_no_access_token();
+            complete(on_complete, false);
         }
     }
+
+    /// Records a deploy using Rollbar's deploy API, returning its ID.
+    pub(crate) fn send_deploy(&self, access_token: &str, deploy: &DeployRequest<'_>) -> Result<u64, Error> {
+        let client = self.client.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let mut req = client.post(self.config.deploy_endpoint()).json(deploy);
+
+        if let Some(mut access_token) = reqwest::header::HeaderValue::from_str(access_token).ok() {
+            access_token.set_sensitive(true);
+            req = req.header("X-Rollbar-Access-Token", access_token);
+        }
+
+        let resp = req.send().map_err(deploy_failed)?;
+        let status = resp.status();
+        let body = resp.bytes().map_err(deploy_failed)?;
+        check_deploy_response(status, &body)
+    }
 }
 
 #[cfg(feature = "threaded")]
@@ -205,6 +1267,103 @@ impl Drop for ThreadedTransport {
     }
 }
 
+/// A transport which records the items it is asked to send, for use in tests.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub (in crate) struct MockTransport {
+    items: Arc<Mutex<Vec<Item>>>,
+    spool: Option<Spool>,
+    undeliverable: bool,
+}
+
+#[cfg(test)]
+impl MockTransport {
+    /// Creates a mock transport which uses the provided spool.
+    pub fn with_spool(spool: Spool) -> Self {
+        Self {
+            spool: Some(spool),
+            ..Default::default()
+        }
+    }
+
+    /// Causes the transport to report every event as undelivered to delivery callbacks.
+    pub fn undeliverable(mut self) -> Self {
+        self.undeliverable = true;
+        self
+    }
+
+    /// Gets the items which have been sent using this transport.
+    pub fn items(&self) -> Vec<Item> {
+        self.items.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl Transport for MockTransport {
+    fn new(_config: &TransportConfig) -> Result<Self, Error> {
+        Ok(Self::default())
+    }
+
+    fn send(&self, event: TransportEvent) {
+        self.items.lock().unwrap().push(event.payload);
+    }
+
+    fn send_with_callback(&self, event: TransportEvent, on_complete: DeliveryCallback) {
+        self.send(event);
+        on_complete(!self.undeliverable);
+    }
+
+    fn spool(&self) -> Option<&Spool> {
+        self.spool.as_ref()
+    }
+}
+
+/// A request to record a deploy using Rollbar's deploy API.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DeployRequest<'a> {
+    pub environment: &'a str,
+    pub revision: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DeployResponse {
+    #[serde(default)]
+    err: u8,
+    data: Option<DeployResponseData>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DeployResponseData {
+    deploy_id: u64,
+}
+
+/// Extracts the ID of the deploy which was recorded from Rollbar's response.
+fn check_deploy_response(status: reqwest::StatusCode, body: &[u8]) -> Result<u64, Error> {
+    match serde_json::from_slice::<DeployResponse>(body) {
+        Ok(DeployResponse { err: 0, data: Some(data), .. }) if status.is_success() => Ok(data.deploy_id),
+        Ok(r) => Err(user(
+            &format!("Rollbar rejected the deploy we sent to it ({}): {}", status, r.message.as_deref().unwrap_or("no reason was provided")),
+            "Make sure that your access token is valid and has the post_server_item scope, then try again.",
+        )),
+        Err(e) => Err(system_with_internal(
+            &format!("We could not understand the response Rollbar sent after recording the deploy ({}).", status),
+            "Make sure that your deploy endpoint points to a Rollbar compatible API and try again.",
+            e
+        )),
+    }
+}
+
+fn deploy_failed(err: reqwest::Error) -> Error {
+    system_with_internal(
+        "We could not send the deploy to Rollbar.",
+        "Make sure that Rollbar is reachable and try again.",
+        err
+    )
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct RollbarResponse {
     err: u8,
@@ -236,7 +1395,7 @@ mod tests {
         let transport = ThreadedTransport::new(&TransportConfig {
             endpoint: server.url("/api/1/item/").to_string(),
             timeout: Duration::from_millis(100),
-            proxy: None,
+            ..Default::default()
         }).unwrap();
 
         let config = Configuration {
@@ -250,9 +1409,838 @@ mod tests {
             config: &config,
             payload: models::Item {
                 data: rollbar_format!(message = "Test message"),
+                ..Default::default()
             },
         });
 
         debug!("Item queued for send to Rollbar");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_batch_endpoint() {
+        assert_eq!(TransportConfig::default().batch_endpoint(), "https://api.rollbar.com/api/1/item_batch/");
+
+        let config = TransportConfig::from_base_url("https://rollbar.example.com/proxy/");
+        assert_eq!(config.endpoint, "https://rollbar.example.com/proxy/api/1/item/");
+        assert_eq!(config.batch_endpoint(), "https://rollbar.example.com/proxy/api/1/item_batch/");
+
+        let config = TransportConfig {
+            item_batch_endpoint: Some("https://batch.example.com/".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.batch_endpoint(), "https://batch.example.com/");
+    }
+
+    #[test]
+    fn test_deploy_endpoint() {
+        assert_eq!(TransportConfig::default().deploy_endpoint(), "https://api.rollbar.com/api/1/deploy/");
+
+        let config = TransportConfig::from_base_url("https://rollbar.example.com/proxy/");
+        assert_eq!(config.deploy_endpoint(), "https://rollbar.example.com/proxy/api/1/deploy/");
+
+        let config = TransportConfig {
+            deploy_endpoint: Some("https://deploy.example.com/".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.deploy_endpoint(), "https://deploy.example.com/");
+    }
+
+    #[test]
+    fn test_warn_missing_token() {
+        let warned = AtomicBool::new(false);
+        assert!(warn_missing_token(&warned), "the first call should emit a warning");
+        assert!(!warn_missing_token(&warned), "subsequent calls should not emit a warning");
+        assert!(!warn_missing_token(&warned), "subsequent calls should not emit a warning");
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_custom_user_agent() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/api/1/item/"),
+                request::headers(contains(("user-agent", "my-wrapper/1.0"))),
+            ])
+            .respond_with(status_code(200))
+        );
+
+        let transport = ThreadedTransport::new(&TransportConfig {
+            endpoint: server.url("/api/1/item/").to_string(),
+            user_agent: Some("my-wrapper/1.0".to_string()),
+            ..Default::default()
+        }).unwrap();
+
+        let config = Configuration {
+            access_token: Some("12345".to_string()),
+            ..Default::default()
+        };
+
+        transport.send(TransportEvent {
+            config: &config,
+            payload: (rollbar_format!(message = "Test message"), &config).into(),
+        });
+    }
+
+    #[test]
+    fn test_endpoint_for() {
+        let config = TransportConfig {
+            endpoints_by_environment: [("staging".to_string(), "https://staging.example.com/api/1/item/".to_string())].into_iter().collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.endpoint_for(Some("staging")), "https://staging.example.com/api/1/item/");
+        assert_eq!(config.endpoint_for(Some("production")), "https://api.rollbar.com/api/1/item/");
+        assert_eq!(config.endpoint_for(None), "https://api.rollbar.com/api/1/item/");
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_endpoints_by_environment() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+            .times(1)
+            .respond_with(status_code(200))
+        );
+
+        let staging = Server::run();
+        staging.expect(
+            Expectation::matching(request::method_path("POST", "/staging/api/1/item/"))
+            .times(1)
+            .respond_with(status_code(200))
+        );
+
+        {
+            let transport = ThreadedTransport::new(&TransportConfig {
+                endpoint: server.url("/api/1/item/").to_string(),
+                endpoints_by_environment: [("staging".to_string(), staging.url("/staging/api/1/item/").to_string())].into_iter().collect(),
+                ..Default::default()
+            }).unwrap();
+
+            let config = Configuration {
+                access_token: Some("12345".to_string()),
+                ..Default::default()
+            };
+
+            for environment in ["staging", "production"] {
+                transport.send(TransportEvent {
+                    config: &config,
+                    payload: (rollbar_format!(message = "Test message", environment = environment), &config).into(),
+                });
+            }
+        }
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_on_serialize() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .respond_with(status_code(200))
+        );
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+
+        {
+            let captured = captured.clone();
+            let transport = ThreadedTransport::new(&TransportConfig {
+                endpoint: server.url("/api/1/item/").to_string(),
+                on_serialize: Some(Arc::new(move |body: &str| captured.lock().unwrap().push(body.to_string()))),
+                ..Default::default()
+            }).unwrap();
+
+            let config = Configuration {
+                access_token: Some("12345".to_string()),
+                ..Default::default()
+            };
+
+            transport.send(TransportEvent {
+                config: &config,
+                payload: (rollbar_format!(message = "Test message"), &config).into(),
+            });
+        }
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 1);
+
+        let body: serde_json::Value = serde_json::from_str(&captured[0]).expect("the body should be valid JSON");
+        assert_eq!(body["data"]["body"]["message"]["body"], "Test message");
+    }
+
+    #[test]
+    #[cfg(feature = "threaded")]
+    fn test_connect_timeout() {
+        let transport = ThreadedTransport::new(&TransportConfig {
+            connect_timeout: Some(Duration::from_millis(500)),
+            ..Default::default()
+        });
+
+        assert!(transport.is_ok(), "the transport should be created with a connect timeout");
+    }
+
+    #[test]
+    #[cfg(feature = "threaded")]
+    fn test_root_cert_pem() {
+        let transport = ThreadedTransport::new(&TransportConfig {
+            root_cert_pem: Some(include_bytes!("../tests/fixtures/ca.pem").to_vec()),
+            ..Default::default()
+        });
+
+        assert!(transport.is_ok(), "the transport should be created with a custom root certificate");
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_local_address() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .respond_with(status_code(200))
+        );
+
+        let transport = ThreadedTransport::new(&TransportConfig {
+            endpoint: server.url("/api/1/item/").to_string(),
+            local_address: Some(IpAddr::V4(std::net::Ipv4Addr::LOCALHOST)),
+            ip_family: IpFamily::V4,
+            ..Default::default()
+        }).unwrap();
+
+        let config = Configuration {
+            access_token: Some("12345".to_string()),
+            ..Default::default()
+        };
+
+        transport.send(TransportEvent {
+            config: &config,
+            payload: (rollbar_format!(message = "Test message"), &config).into(),
+        });
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_resolve() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/api/1/item/"),
+                request::headers(contains(("host", matches("^rollbar\\.example\\.com:")))),
+            ])
+            .respond_with(status_code(200))
+        );
+
+        let transport = ThreadedTransport::new(&TransportConfig {
+            endpoint: format!("http://rollbar.example.com:{}/api/1/item/", server.addr().port()),
+            resolve: vec![("rollbar.example.com".to_string(), SocketAddr::new(IpAddr::V4(std::net::Ipv4Addr::LOCALHOST), server.addr().port()))],
+            ..Default::default()
+        }).unwrap();
+
+        let config = Configuration {
+            access_token: Some("12345".to_string()),
+            ..Default::default()
+        };
+
+        transport.send(TransportEvent {
+            config: &config,
+            payload: (rollbar_format!(message = "Test message"), &config).into(),
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "threaded")]
+    fn test_classify_send_failure() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/slow"))
+                .respond_with(httptest::responders::delay_and_then(Duration::from_secs(2), status_code(200)))
+        );
+
+        let client = BlockingClient::builder().timeout(Duration::from_millis(100)).build().unwrap();
+
+        let err = client.post(server.url("/slow").to_string()).send().unwrap_err();
+        assert_eq!(SendFailure::classify(&err), SendFailure::Timeout);
+        assert!(send_failed(err, None).1, "timeouts should be retryable");
+
+        let unused_port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let err = client.post(format!("http://127.0.0.1:{}/", unused_port)).send().unwrap_err();
+        assert_eq!(SendFailure::classify(&err), SendFailure::Connect);
+        assert!(send_failed(err, None).1, "connection failures should be retryable");
+
+        let err = client.post("not a url").send().unwrap_err();
+        assert_eq!(SendFailure::classify(&err), SendFailure::Other);
+        let (result, retryable) = send_failed(err, None);
+        assert!(result.is_err());
+        assert!(!retryable, "other failures should not be retryable");
+
+        for (path, status) in [("/corrupt", 200), ("/corrupt-error", 503)] {
+            server.expect(
+                Expectation::matching(request::method_path("POST", path))
+                    .respond_with(status_code(status).insert_header("Content-Encoding", "gzip").body("this is not gzip"))
+            );
+        }
+
+        let resp = client.post(server.url("/corrupt").to_string()).send().unwrap();
+        let status = resp.status();
+        let err = resp.bytes().unwrap_err();
+        assert_eq!(SendFailure::classify(&err), SendFailure::Decode);
+        let (result, retryable) = send_failed(err, Some(status));
+        assert!(result.is_ok(), "a successful response which cannot be decoded should be treated as delivered");
+        assert!(!retryable);
+
+        let resp = client.post(server.url("/corrupt-error").to_string()).send().unwrap();
+        let status = resp.status();
+        let err = resp.bytes().unwrap_err();
+        assert_eq!(SendFailure::classify(&err), SendFailure::Decode);
+        let (result, retryable) = send_failed(err, Some(status));
+        assert!(result.is_err(), "an unsuccessful response which cannot be decoded should not be treated as delivered");
+        assert!(retryable, "the response's status should determine whether it is retried");
+    }
+
+    #[test]
+    #[cfg(feature = "msgpack")]
+    fn test_msgpack_serialization() {
+        let config = Configuration::default();
+        let item: Item = (rollbar_format!(message = "Test message"), &config).into();
+
+        let body = serialize_item(&item, SerializationFormat::MessagePack, None, None).unwrap();
+        assert_ne!(body, serialize_item(&item, SerializationFormat::Json, None, None).unwrap());
+
+        let decoded: Item = rmp_serde::from_slice(&body).unwrap();
+        assert_eq!(decoded.data.uuid, item.data.uuid);
+        assert_eq!(decoded.schema_version, item.schema_version);
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(&item).unwrap());
+    }
+
+    #[test]
+    fn test_serialize_fallback() {
+        let config = Configuration::default();
+        let item: Item = (rollbar_format!(message = "Test message", custom = map!{ problematic: "value" }), &config).into();
+
+        let fails_with_custom = |item: &Item, format: SerializationFormat| {
+            match item.data.custom.as_ref().map(|c| c.contains_key("problematic")) {
+                Some(true) => Err("the custom data could not be serialized".to_string()),
+                _ => encode_item(item, format),
+            }
+        };
+
+        let body = serialize_item_with(&item, SerializationFormat::Json, None, None, fails_with_custom).unwrap();
+        let sent: Item = serde_json::from_slice(&body).unwrap();
+        assert_eq!(sent.data.uuid, item.data.uuid, "the degraded report should still be sent");
+        assert_eq!(sent.data.custom, Some(map!{ serialization_error: "the custom data could not be serialized" }));
+
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let on_error: ErrorHook = {
+            let errors = errors.clone();
+            Arc::new(move |err: &Error| errors.lock().unwrap().push(err.to_string()))
+        };
+
+        let always_fails = |_: &Item, _: SerializationFormat| Err("serialization failed".to_string());
+        assert!(serialize_item_with(&item, SerializationFormat::Json, None, Some(&on_error), always_fails).is_none());
+        assert_eq!(errors.lock().unwrap().len(), 1, "serialization errors should be passed to the error hook");
+    }
+
+    #[test]
+    fn test_compress_body() {
+        let body = br#"{"data": {"body": {"message": {"body": "Test message"}}}}"#.to_vec();
+
+        assert_eq!(compress_body(body.clone(), false), (body.clone(), false));
+
+        let (compressed, is_compressed) = compress_body(body.clone(), true);
+        assert!(is_compressed);
+        assert_eq!(&compressed[..2], &[0x1f, 0x8b], "the body should be gzip encoded");
+
+        let mut decompressed = Vec::new();
+        std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(compressed.as_slice()), &mut decompressed).unwrap();
+        assert_eq!(decompressed, body);
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_compress_requests() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/api/1/item/"),
+                request::headers(contains(("content-encoding", "gzip"))),
+            ])
+            .respond_with(status_code(200))
+        );
+
+        let transport = ThreadedTransport::new(&TransportConfig {
+            endpoint: server.url("/api/1/item/").to_string(),
+            compress_requests: true,
+            ..Default::default()
+        }).unwrap();
+
+        let config = Configuration {
+            access_token: Some("12345".to_string()),
+            ..Default::default()
+        };
+
+        transport.send(TransportEvent {
+            config: &config,
+            payload: (rollbar_format!(message = "Test message"), &config).into(),
+        });
+    }
+
+    #[test]
+    fn test_check_response() {
+        assert!(check_response(reqwest::StatusCode::OK, br#"{"err": 0, "result": {"uuid": "1234"}}"#).is_ok());
+        assert!(check_response(reqwest::StatusCode::OK, b"").is_ok());
+
+        let err = check_response(reqwest::StatusCode::OK, br#"{"err": 1, "message": "invalid payload"}"#).unwrap_err();
+        assert!(err.to_string().contains("invalid payload"));
+
+        let err = check_response(reqwest::StatusCode::FORBIDDEN, b"Forbidden").unwrap_err();
+        assert!(err.to_string().contains("403"));
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_rejected_event() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .respond_with(status_code(403).body(r#"{"err": 1, "message": "invalid access token"}"#))
+        );
+
+        let errors = Arc::new(Mutex::new(Vec::new()));
+
+        {
+            let errors = errors.clone();
+            let transport = ThreadedTransport::new(&TransportConfig {
+                endpoint: server.url("/api/1/item/").to_string(),
+                on_error: Some(Arc::new(move |err: &Error| errors.lock().unwrap().push(err.to_string()))),
+                ..Default::default()
+            }).unwrap();
+
+            let config = Configuration {
+                access_token: Some("12345".to_string()),
+                ..Default::default()
+            };
+
+            transport.send(TransportEvent {
+                config: &config,
+                payload: (rollbar_format!(message = "Test message"), &config).into(),
+            });
+        }
+
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("invalid access token"), "the error should include Rollbar's message");
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_circuit_breaker() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .times(2)
+                .respond_with(status_code(500))
+        );
+
+        let transport = ThreadedTransport::new(&TransportConfig {
+            endpoint: server.url("/api/1/item/").to_string(),
+            circuit_breaker_threshold: Some(2),
+            circuit_breaker_cooldown: Duration::from_secs(60),
+            ..Default::default()
+        }).unwrap();
+
+        let config = Configuration {
+            access_token: Some("12345".to_string()),
+            ..Default::default()
+        };
+
+        for _ in 0..5 {
+            transport.send(TransportEvent {
+                config: &config,
+                payload: (rollbar_format!(message = "Test message"), &config).into(),
+            });
+        }
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while transport.metrics().dropped < 3 && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(transport.metrics().dropped, 3, "events should be dropped once the circuit breaker opens");
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_circuit_breaker_ignores_rejected_events() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .times(4)
+                .respond_with(status_code(400))
+        );
+
+        let transport = ThreadedTransport::new(&TransportConfig {
+            endpoint: server.url("/api/1/item/").to_string(),
+            circuit_breaker_threshold: Some(2),
+            circuit_breaker_cooldown: Duration::from_secs(60),
+            ..Default::default()
+        }).unwrap();
+
+        let config = Configuration {
+            access_token: Some("12345".to_string()),
+            ..Default::default()
+        };
+
+        for _ in 0..4 {
+            transport.send(TransportEvent {
+                config: &config,
+                payload: (rollbar_format!(message = "Test message"), &config).into(),
+            });
+        }
+
+        // Dropping the transport waits for the queued events to be sent.
+        let metrics = transport.metrics.clone();
+        drop(transport);
+
+        assert_eq!(metrics.snapshot().dropped, 0, "events rejected by Rollbar should not open the circuit breaker");
+        server.verify_and_clear();
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_async_max_concurrent() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .times(2)
+                .respond_with(httptest::responders::delay_and_then(Duration::from_millis(200), status_code(200)))
+        );
+
+        // The `async_before_send` hook runs while an event holds its permit, so it is used
+        // to track how many events are being sent at once.
+        let active = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let peak = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let (hook_active, hook_peak) = (active.clone(), peak.clone());
+            let transport = TokioTransport::new(&TransportConfig {
+                endpoint: server.url("/api/1/item/").to_string(),
+                max_concurrent: Some(1),
+                overflow_policy: OverflowPolicy::Queue { capacity: 1 },
+                async_before_send: Some(Arc::new(move |data: crate::types::Data| -> std::pin::Pin<Box<dyn std::future::Future<Output = crate::types::Data> + Send>> {
+                    let (active, peak) = (hook_active.clone(), hook_peak.clone());
+                    Box::pin(async move {
+                        let current = active.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(current, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        data
+                    })
+                })),
+                ..Default::default()
+            }).unwrap();
+
+            let config = Configuration {
+                access_token: Some("12345".to_string()),
+                ..Default::default()
+            };
+
+            for _ in 0..5 {
+                transport.send(TransportEvent {
+                    config: &config,
+                    payload: (rollbar_format!(message = "Test message"), &config).into(),
+                });
+            }
+
+            assert_eq!(transport.metrics().dropped, 3, "events beyond the concurrency limit and queue capacity should be dropped");
+
+            let limiter = transport.limiter.clone().unwrap();
+            let deadline = std::time::Instant::now() + Duration::from_secs(5);
+            while limiter.semaphore.available_permits() < 1 || limiter.queued.load(Ordering::SeqCst) > 0 {
+                assert!(std::time::Instant::now() < deadline, "the queued events should be sent");
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+
+        assert_eq!(peak.load(Ordering::SeqCst), 1, "no more than max_concurrent events should be sent at once");
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_async_endpoints_by_environment() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+            .times(1)
+            .respond_with(status_code(200))
+        );
+
+        let staging = Server::run();
+        staging.expect(
+            Expectation::matching(request::method_path("POST", "/staging/api/1/item/"))
+            .times(1)
+            .respond_with(status_code(200))
+        );
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let transport = TokioTransport::new(&TransportConfig {
+                endpoint: server.url("/api/1/item/").to_string(),
+                endpoints_by_environment: [("staging".to_string(), staging.url("/staging/api/1/item/").to_string())].into_iter().collect(),
+                ..Default::default()
+            }).unwrap();
+
+            let config = Configuration {
+                access_token: Some("12345".to_string()),
+                ..Default::default()
+            };
+
+            for environment in ["staging", "production"] {
+                transport.send(TransportEvent {
+                    config: &config,
+                    payload: (rollbar_format!(message = "Test message", environment = environment), &config).into(),
+                });
+            }
+
+            transport.flush(Duration::from_secs(5)).await.expect("the flush should complete");
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_async_flush() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .times(1)
+                .respond_with(httptest::responders::delay_and_then(Duration::from_millis(200), status_code(200)))
+        );
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let transport = TokioTransport::new(&TransportConfig {
+                endpoint: server.url("/api/1/item/").to_string(),
+                ..Default::default()
+            }).unwrap();
+
+            let config = Configuration {
+                access_token: Some("12345".to_string()),
+                ..Default::default()
+            };
+
+            transport.send(TransportEvent {
+                config: &config,
+                payload: (rollbar_format!(message = "Test message"), &config).into(),
+            });
+
+            assert!(transport.flush(Duration::from_millis(10)).await.is_err(), "the flush should time out while the send is in progress");
+
+            let started = std::time::Instant::now();
+            transport.flush(Duration::from_secs(5)).await.expect("the flush should complete");
+            assert!(started.elapsed() >= Duration::from_millis(100), "the flush should wait for the slow send to complete");
+            assert_eq!(transport.in_flight.count.load(Ordering::SeqCst), 0);
+
+            transport.flush(Duration::from_millis(10)).await.expect("flushing an idle transport should complete immediately");
+        });
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_reset() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .times(2)
+                .respond_with(status_code(200))
+        );
+
+        let transport = ThreadedTransport::new(&TransportConfig {
+            endpoint: server.url("/api/1/item/").to_string(),
+            ..Default::default()
+        }).unwrap();
+
+        let config = Configuration {
+            access_token: Some("12345".to_string()),
+            ..Default::default()
+        };
+
+        transport.send(TransportEvent {
+            config: &config,
+            payload: (rollbar_format!(message = "Before reset"), &config).into(),
+        });
+
+        transport.reset().expect("the client should be rebuilt");
+
+        transport.send(TransportEvent {
+            config: &config,
+            payload: (rollbar_format!(message = "After reset"), &config).into(),
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_async_reset() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .times(2)
+                .respond_with(status_code(200))
+        );
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let transport = TokioTransport::new(&TransportConfig {
+                endpoint: server.url("/api/1/item/").to_string(),
+                ..Default::default()
+            }).unwrap();
+
+            let config = Configuration {
+                access_token: Some("12345".to_string()),
+                ..Default::default()
+            };
+
+            transport.send(TransportEvent {
+                config: &config,
+                payload: (rollbar_format!(message = "Before reset"), &config).into(),
+            });
+
+            transport.reset().expect("the client should be rebuilt");
+
+            transport.send(TransportEvent {
+                config: &config,
+                payload: (rollbar_format!(message = "After reset"), &config).into(),
+            });
+
+            transport.flush(Duration::from_secs(5)).await.expect("the events should be sent");
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_async_before_send() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .respond_with(status_code(200))
+        );
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let captured = captured.clone();
+            let transport = TokioTransport::new(&TransportConfig {
+                endpoint: server.url("/api/1/item/").to_string(),
+                on_serialize: Some(Arc::new(move |body: &str| captured.lock().unwrap().push(body.to_string()))),
+                async_before_send: Some(Arc::new(|mut data: crate::types::Data| -> std::pin::Pin<Box<dyn std::future::Future<Output = crate::types::Data> + Send>> {
+                    Box::pin(async move {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        data.person = Some(crate::Person::new("12345"));
+                        data
+                    })
+                })),
+                ..Default::default()
+            }).unwrap();
+
+            let config = Configuration {
+                access_token: Some("12345".to_string()),
+                ..Default::default()
+            };
+
+            transport.send(TransportEvent {
+                config: &config,
+                payload: (rollbar_format!(message = "Test message"), &config).into(),
+            });
+
+            transport.flush(Duration::from_secs(5)).await.expect("the event should be sent");
+        });
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 1);
+
+        let body: serde_json::Value = serde_json::from_str(&captured[0]).expect("the body should be valid JSON");
+        assert_eq!(body["data"]["person"]["id"], "12345", "the changes made by the hook should be sent");
+        assert_eq!(body["data"]["body"]["message"]["body"], "Test message");
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_worker_panic_recovery() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .times(1)
+                .respond_with(status_code(200))
+        );
+
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let panicked = Arc::new(AtomicBool::new(false));
+
+        {
+            let errors = errors.clone();
+            let panicked = panicked.clone();
+            let transport = ThreadedTransport::new(&TransportConfig {
+                endpoint: server.url("/api/1/item/").to_string(),
+                on_serialize: Some(Arc::new(move |_: &str| {
+                    if !panicked.swap(true, Ordering::SeqCst) {
+                        panic!("The serialize hook panicked");
+                    }
+                })),
+                on_error: Some(Arc::new(move |err: &Error| errors.lock().unwrap().push(err.to_string()))),
+                ..Default::default()
+            }).unwrap();
+
+            let config = Configuration {
+                access_token: Some("12345".to_string()),
+                ..Default::default()
+            };
+
+            for _ in 0..2 {
+                transport.send(TransportEvent {
+                    config: &config,
+                    payload: (rollbar_format!(message = "Test message"), &config).into(),
+                });
+            }
+        }
+
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 1, "the panic should be surfaced through the error hook");
+        assert!(errors[0].contains("The serialize hook panicked"));
+    }
+
+    #[test_log::test]
+    #[cfg(feature = "threaded")]
+    fn test_send_duration_metrics() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/1/item/"))
+                .respond_with(httptest::responders::delay_and_then(Duration::from_millis(100), status_code(200)))
+        );
+
+        let transport = ThreadedTransport::new(&TransportConfig {
+            endpoint: server.url("/api/1/item/").to_string(),
+            ..Default::default()
+        }).unwrap();
+
+        assert_eq!(transport.metrics().last_send_duration, None);
+
+        let config = Configuration {
+            access_token: Some("12345".to_string()),
+            ..Default::default()
+        };
+
+        transport.send(TransportEvent {
+            config: &config,
+            payload: (rollbar_format!(message = "Test message"), &config).into(),
+        });
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while transport.metrics().last_send_duration.is_none() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let metrics = transport.metrics();
+        assert!(metrics.last_send_duration.unwrap() >= Duration::from_millis(100), "the send duration should include the server's delay");
+        assert_eq!(metrics.average_send_duration, metrics.last_send_duration);
+    }
+}